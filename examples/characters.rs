@@ -4,7 +4,10 @@
 use sqlite_loadable::prelude::*;
 use sqlite_loadable::{
     api, define_table_function,
-    table::{BestIndexError, ConstraintOperator, IndexInfo, VTab, VTabArguments, VTabCursor},
+    table::{
+        vtab_config, BestIndexError, ConstraintOperator, IndexInfo, VTab, VTabArguments,
+        VTabConfig, VTabCursor,
+    },
     Result,
 };
 
@@ -34,19 +37,19 @@ impl<'vtab> VTab<'vtab> for CharactersTable {
     type Cursor = CharactersCursor;
 
     fn connect(
-        _db: *mut sqlite3,
+        db: *mut sqlite3,
         _aux: Option<&Self::Aux>,
         _args: VTabArguments,
     ) -> Result<(String, CharactersTable)> {
         let vtab = CharactersTable { base: unsafe { mem::zeroed() } };
-        // TODO db.config(VTabConfig::Innocuous)?;
+        vtab_config(db, VTabConfig::Innocuous)?;
         Ok((CREATE_SQL.to_owned(), vtab))
     }
-    fn destroy(&self) -> Result<()> {
+    fn destroy(&mut self) -> Result<()> {
         Ok(())
     }
 
-    fn best_index(&self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+    fn best_index(&mut self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
         let mut has_input = false;
         for mut constraint in info.constraints() {
             match column(constraint.column_idx()) {
@@ -1,9 +1,12 @@
-use proc_macro2::Ident;
+use proc_macro2::{Ident, TokenStream as TokenStream2};
 
-use syn::{parse_macro_input, spanned::Spanned, Item};
+use syn::{
+    parse_macro_input, parse_quote, spanned::Spanned, Attribute, AttributeArgs, Item, ItemFn,
+    ItemMod, Lit, Meta, NestedMeta,
+};
 
 use proc_macro::TokenStream;
-use quote::quote_spanned;
+use quote::{format_ident, quote, quote_spanned};
 
 /// Wraps an entrypoint function to expose an unsafe extern "C" function of the same name.
 #[proc_macro_attribute]
@@ -84,3 +87,212 @@ pub fn sqlite_entrypoint_permanent(_attr: TokenStream, item: TokenStream) -> Tok
         _ => panic!("Only function items are allowed on sqlite_entrypoint"),
     }
 }
+
+/// Defines a plain Rust function as a SQLite scalar function. The function's
+/// arguments and return value are converted with `FromSqlValue`/`ToSqlResult`,
+/// same as `define_typed_scalar_function`, so it must return a `Result`.
+///
+/// Options:
+/// - `name = "..."`: the SQL name of the function, defaults to the Rust name.
+/// - `deterministic`, `innocuous`, `direct_only`, `subtype`: adds the matching `FunctionFlags`.
+///
+/// The function has to be inside a module marked with [`macro@sqlite_functions`],
+/// which registers all of them with a single `register_functions(db)` call.
+/// Anywhere else it's a compile error, since nothing would register it.
+///
+/// ```rust,compile_fail
+/// use sqlite_loadable::prelude::*;
+/// use sqlite_loadable::Result;
+///
+/// #[sqlite_function(name = "xyz_add", deterministic, innocuous)]
+/// fn add(a: i64, b: i64) -> Result<i64> {
+///     Ok(a + b)
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn sqlite_function(_attr: TokenStream, item: TokenStream) -> TokenStream {
+    // #[sqlite_functions] expands and removes this attribute on the functions it
+    // collects, so this only runs for functions that nothing registers
+    let func = parse_macro_input!(item as ItemFn);
+    let error = syn::Error::new(
+        func.sig.ident.span(),
+        "#[sqlite_function] has to be inside a #[sqlite_functions] module, which registers it",
+    )
+    .to_compile_error();
+    quote!(#func #error).into()
+}
+
+/// Collects every [`macro@sqlite_function`] inside an inline module, including in
+/// nested inline modules, and generates a
+/// `pub fn register_functions(db: *mut sqlite3) -> Result<()>` in that module that
+/// defines all of them on the given connection. Nested modules with their own
+/// `#[sqlite_functions]` are registered through their `register_functions`.
+///
+/// ```rust
+/// use sqlite_loadable::prelude::*;
+/// use sqlite_loadable::Result;
+///
+/// #[sqlite_functions]
+/// mod functions {
+///     use sqlite_loadable::prelude::*;
+///     use sqlite_loadable::Result;
+///
+///     #[sqlite_function(name = "xyz_add", deterministic)]
+///     fn add(a: i64, b: i64) -> Result<i64> {
+///         Ok(a + b)
+///     }
+/// }
+///
+/// #[sqlite_entrypoint]
+/// pub fn sqlite3_xyz_init(db: *mut sqlite3) -> Result<()> {
+///     functions::register_functions(db)
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn sqlite_functions(_attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut module = parse_macro_input!(item as ItemMod);
+    let items = match module.content.as_mut() {
+        Some((_, items)) => items,
+        None => {
+            return syn::Error::new(
+                module.span(),
+                "sqlite_functions only works on inline modules, like `mod functions { ... }`",
+            )
+            .to_compile_error()
+            .into()
+        }
+    };
+
+    let mut registrations = vec![];
+    if let Err(err) = collect_functions(items, &[], &mut registrations) {
+        return err.to_compile_error().into();
+    }
+
+    items.push(parse_quote! {
+        /// Defines every `#[sqlite_function]` in this module on the given database connection.
+        pub fn register_functions(
+            db: *mut ::sqlite_loadable::prelude::sqlite3,
+        ) -> ::sqlite_loadable::Result<()> {
+            #( #registrations(db)?; )*
+            ::std::result::Result::Ok(())
+        }
+    });
+
+    quote!(#module).into()
+}
+
+/// Expands every `#[sqlite_function]` in `items`, and recursively in their inline
+/// modules, and adds the path of the function that registers each one, relative
+/// to the `#[sqlite_functions]` module, to `registrations`.
+fn collect_functions(
+    items: &mut Vec<Item>,
+    path: &[Ident],
+    registrations: &mut Vec<TokenStream2>,
+) -> syn::Result<()> {
+    let mut generated = vec![];
+    for item in items.iter_mut() {
+        match item {
+            Item::Fn(func) => {
+                let position = func.attrs.iter().position(is_sqlite_function_attribute);
+                if let Some(position) = position {
+                    let attr = func.attrs.remove(position);
+                    let args = match attr.parse_meta()? {
+                        Meta::Path(_) => vec![],
+                        Meta::List(list) => list.nested.into_iter().collect(),
+                        meta => {
+                            return Err(syn::Error::new(
+                                meta.span(),
+                                "expected #[sqlite_function] or #[sqlite_function(...)]",
+                            ))
+                        }
+                    };
+                    generated.push(function_registration(args, func)?);
+                    let register = registration_ident(&func.sig.ident);
+                    registrations.push(quote!(#(#path::)* #register));
+                }
+            }
+            Item::Mod(module) => {
+                let ident = &module.ident;
+                if module.attrs.iter().any(is_sqlite_functions_attribute) {
+                    registrations.push(quote!(#(#path::)* #ident::register_functions));
+                } else if let Some((_, items)) = module.content.as_mut() {
+                    let mut path = path.to_vec();
+                    path.push(ident.clone());
+                    collect_functions(items, &path, registrations)?;
+                }
+            }
+            _ => (),
+        }
+    }
+    items.extend(generated);
+    Ok(())
+}
+
+fn is_sqlite_function_attribute(attr: &Attribute) -> bool {
+    matches!(attr.path.segments.last(), Some(segment) if segment.ident == "sqlite_function")
+}
+
+fn is_sqlite_functions_attribute(attr: &Attribute) -> bool {
+    matches!(attr.path.segments.last(), Some(segment) if segment.ident == "sqlite_functions")
+}
+
+/// Name of the hidden function that defines the `#[sqlite_function]` named `ident`.
+fn registration_ident(ident: &Ident) -> Ident {
+    format_ident!("__sqlite_function_register_{}", ident)
+}
+
+/// The hidden function that defines `func` on a database connection, visible to
+/// the whole crate so that a `#[sqlite_functions]` module can call it from any
+/// nested module.
+fn function_registration(args: AttributeArgs, func: &ItemFn) -> syn::Result<Item> {
+    let mut name = func.sig.ident.to_string();
+    let mut flags = vec![Ident::new("UTF8", func.sig.ident.span())];
+
+    for arg in args {
+        match arg {
+            NestedMeta::Meta(Meta::NameValue(meta)) if meta.path.is_ident("name") => {
+                match meta.lit {
+                    Lit::Str(value) => name = value.value(),
+                    lit => return Err(syn::Error::new(lit.span(), "expected a string")),
+                }
+            }
+            NestedMeta::Meta(Meta::Path(path)) => {
+                let flag = match path.get_ident().map(|ident| ident.to_string()).as_deref() {
+                    Some("deterministic") => "DETERMINISTIC",
+                    Some("innocuous") => "INNOCUOUS",
+                    Some("direct_only") => "DIRECTONLY",
+                    Some("subtype") => "SUBTYPE",
+                    _ => {
+                        return Err(syn::Error::new(
+                            path.span(),
+                            "unknown sqlite_function option, expected one of: name, deterministic, innocuous, direct_only, subtype",
+                        ))
+                    }
+                };
+                flags.push(Ident::new(flag, path.span()));
+            }
+            other => {
+                return Err(syn::Error::new(
+                    other.span(),
+                    "unknown sqlite_function option",
+                ))
+            }
+        }
+    }
+
+    let ident = &func.sig.ident;
+    let register = registration_ident(ident);
+    Ok(parse_quote! {
+        #[doc(hidden)]
+        pub(crate) fn #register(
+            db: *mut ::sqlite_loadable::prelude::sqlite3,
+        ) -> ::sqlite_loadable::Result<()> {
+            ::sqlite_loadable::define_typed_scalar_function(
+                db,
+                #name,
+                #ident,
+                #( ::sqlite_loadable::FunctionFlags::#flags )|*,
+            )
+        }
+    })
+}
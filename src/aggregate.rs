@@ -0,0 +1,231 @@
+//! Define aggregate and window functions on sqlite3 database connections.
+
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+use std::{
+    ffi::CString,
+    mem,
+    os::raw::{c_int, c_void},
+    ptr, slice,
+};
+
+use crate::{
+    api,
+    constants::{SQLITE_NOMEM, SQLITE_OKAY},
+    context::Context,
+    errors::{catch_panic, Error, ErrorKind, Result},
+    ext::{
+        sqlite3, sqlite3_context, sqlite3_value, sqlite3ext_aggregate_context,
+        sqlite3ext_create_window_function,
+    },
+    scalar::{create_function_v2, result_error, FunctionFlags},
+};
+
+/// The state of a single aggregate function invocation. A new value is created
+/// with [`Default::default`] for every group that the aggregate function runs
+/// over, so each `GROUP BY` bucket has its own, independent state.
+///
+/// The state is stored inside of
+/// [`sqlite3_aggregate_context`](https://www.sqlite.org/c3ref/aggregate_context.html)
+/// and is dropped after [`Aggregate::finalize`] is called. SQLite always calls
+/// xFinal on an aggregate context that has been allocated, even when a query
+/// errors or is reset early, so the state is never leaked.
+pub trait Aggregate: Default {
+    /// Called once for every row in a group, aka "xStep".
+    /// <https://www.sqlite.org/c3ref/create_function.html>
+    fn step(&mut self, context: &mut Context, values: &[*mut sqlite3_value]) -> Result<()>;
+
+    /// Called once after all rows in a group have been stepped through, aka "xFinal".
+    /// Should result the final value of the aggregate on the given context.
+    /// If the aggregate ran over zero rows, then this is called on a default value.
+    fn finalize(self, context: &mut Context) -> Result<()>;
+}
+
+/// An aggregate that can also be used as an
+/// [aggregate window function](https://www.sqlite.org/windowfunctions.html#udfwinfunc),
+/// where rows are both added to and removed from the current frame.
+pub trait WindowFunction: Aggregate {
+    /// Called to result the current value of the aggregate, aka "xValue".
+    /// Unlike [`Aggregate::finalize`], the state is kept around afterwards.
+    fn value(&self, context: &mut Context) -> Result<()>;
+
+    /// Called to remove the oldest row that was passed into [`Aggregate::step`]
+    /// from the current window, aka "xInverse".
+    fn inverse(&mut self, context: &mut Context, values: &[*mut sqlite3_value]) -> Result<()>;
+}
+
+/// Returns a pointer to the slot inside the aggregate context that holds the
+/// boxed state. If `allocate` is false and no state was ever created (ie the
+/// aggregate ran over 0 rows), then a null pointer is returned.
+unsafe fn aggregate_state_slot<T>(context: *mut sqlite3_context, allocate: bool) -> *mut *mut T {
+    let n_bytes = if allocate {
+        mem::size_of::<*mut T>() as c_int
+    } else {
+        0
+    };
+    // "... the first time sqlite3_aggregate_context(C,N) is called for a particular
+    // aggregate function, SQLite allocates N bytes of memory, zeroes out that memory,
+    // and returns a pointer to the new memory."
+    sqlite3ext_aggregate_context(context, n_bytes).cast::<*mut T>()
+}
+
+/// Returns the state for the current group, creating it if it does not exist yet.
+unsafe fn aggregate_state<'a, T: Aggregate>(context: *mut sqlite3_context) -> Option<&'a mut T> {
+    let slot = aggregate_state_slot::<T>(context, true);
+    if slot.is_null() {
+        return None;
+    }
+    if (*slot).is_null() {
+        *slot = Box::into_raw(Box::<T>::default());
+    }
+    Some(&mut **slot)
+}
+
+/// Takes ownership of the state for the current group, leaving an empty slot behind.
+/// Returns a new default state if the aggregate never stepped.
+unsafe fn take_aggregate_state<T: Aggregate>(context: *mut sqlite3_context) -> T {
+    let slot = aggregate_state_slot::<T>(context, false);
+    if slot.is_null() || (*slot).is_null() {
+        return T::default();
+    }
+    let state = Box::from_raw(*slot);
+    *slot = ptr::null_mut();
+    *state
+}
+
+unsafe extern "C" fn x_step<T: Aggregate>(
+    context: *mut sqlite3_context,
+    argc: c_int,
+    argv: *mut *mut sqlite3_value,
+) {
+    let state = match aggregate_state::<T>(context) {
+        Some(state) => state,
+        None => {
+            api::result_error_code(context, SQLITE_NOMEM);
+            return;
+        }
+    };
+    let args = slice::from_raw_parts(argv, argc as usize);
+    let result = catch_panic(|| state.step(&mut Context::from_raw(context), args));
+    if let Err(err) = result.and_then(|result| result) {
+        result_error(context, err);
+    }
+}
+
+unsafe extern "C" fn x_final<T: Aggregate>(context: *mut sqlite3_context) {
+    let result = catch_panic(|| {
+        take_aggregate_state::<T>(context).finalize(&mut Context::from_raw(context))
+    });
+    if let Err(err) = result.and_then(|result| result) {
+        result_error(context, err);
+    }
+}
+
+unsafe extern "C" fn x_value<T: WindowFunction>(context: *mut sqlite3_context) {
+    // xValue can be called on an empty frame before any xStep, so the state
+    // is created here if needed. xFinal will drop it later on.
+    let state = match aggregate_state::<T>(context) {
+        Some(state) => state,
+        None => {
+            api::result_error_code(context, SQLITE_NOMEM);
+            return;
+        }
+    };
+    let result = catch_panic(|| state.value(&mut Context::from_raw(context)));
+    if let Err(err) = result.and_then(|result| result) {
+        result_error(context, err);
+    }
+}
+
+unsafe extern "C" fn x_inverse<T: WindowFunction>(
+    context: *mut sqlite3_context,
+    argc: c_int,
+    argv: *mut *mut sqlite3_value,
+) {
+    let state = match aggregate_state::<T>(context) {
+        Some(state) => state,
+        None => {
+            api::result_error_code(context, SQLITE_NOMEM);
+            return;
+        }
+    };
+    let args = slice::from_raw_parts(argv, argc as usize);
+    let result = catch_panic(|| state.inverse(&mut Context::from_raw(context), args));
+    if let Err(err) = result.and_then(|result| result) {
+        result_error(context, err);
+    }
+}
+
+/// Defines a new aggregate function on the given database connection, where
+/// `T` holds the per-group state of the aggregate.
+///
+/// # Example
+/// ```rust
+/// #[derive(Default)]
+/// struct Sum {
+///     total: i64,
+/// }
+///
+/// impl Aggregate for Sum {
+///     fn step(&mut self, _context: &mut Context, values: &[*mut sqlite3_value]) -> Result<()> {
+///         self.total += api::value_int64(&values[0]);
+///         Ok(())
+///     }
+///     fn finalize(self, context: &mut Context) -> Result<()> {
+///         context.result_int64(self.total);
+///         Ok(())
+///     }
+/// }
+///
+/// define_aggregate_function::<Sum>(db, "xyz_sum", 1, FunctionFlags::UTF8)?;
+/// ```
+pub fn define_aggregate_function<T: Aggregate>(
+    db: *mut sqlite3,
+    name: &str,
+    num_args: c_int,
+    func_flags: FunctionFlags,
+) -> Result<()> {
+    create_function_v2(
+        db,
+        name,
+        num_args,
+        func_flags,
+        ptr::null_mut::<c_void>(),
+        None,
+        Some(x_step::<T>),
+        Some(x_final::<T>),
+        None,
+    )
+}
+
+/// Defines a new aggregate window function on the given database connection,
+/// with [`sqlite3_create_window_function`](https://www.sqlite.org/c3ref/create_function.html).
+/// The function can be used both as a regular aggregate function and with an
+/// `OVER (...)` clause, where SQLite will call [`WindowFunction::inverse`] as rows
+/// leave the frame instead of re-aggregating the entire frame for every row.
+pub fn define_window_function<T: WindowFunction>(
+    db: *mut sqlite3,
+    name: &str,
+    num_args: c_int,
+    func_flags: FunctionFlags,
+) -> Result<()> {
+    let cname = CString::new(name)?;
+    let result = unsafe {
+        sqlite3ext_create_window_function(
+            db,
+            cname.as_ptr(),
+            num_args,
+            func_flags.bits(),
+            ptr::null_mut(),
+            Some(x_step::<T>),
+            Some(x_final::<T>),
+            Some(x_value::<T>),
+            Some(x_inverse::<T>),
+            None,
+        )
+    };
+    if result != SQLITE_OKAY {
+        Err(Error::new(ErrorKind::DefineScalarFunction(result)))
+    } else {
+        Ok(())
+    }
+}
@@ -5,19 +5,23 @@
 //! Useful when working with sqlite3_value or sqlite3_context.
 #![allow(clippy::not_unsafe_ptr_arg_deref)]
 
-use crate::constants::SQLITE_OKAY;
+use crate::constants::{SQLITE_DONE, SQLITE_OKAY, SQLITE_ROW};
+use crate::errors::{Error, ErrorKind};
 use crate::ext::{
-    sqlite3, sqlite3_context, sqlite3_value, sqlite3ext_context_db_handle, sqlite3ext_get_auxdata,
-    sqlite3ext_mprintf, sqlite3ext_overload_function, sqlite3ext_result_blob,
-    sqlite3ext_result_double, sqlite3ext_result_error, sqlite3ext_result_error_code,
-    sqlite3ext_result_int, sqlite3ext_result_int64, sqlite3ext_result_null,
-    sqlite3ext_result_pointer, sqlite3ext_result_subtype, sqlite3ext_result_text,
-    sqlite3ext_set_auxdata, sqlite3ext_value_blob, sqlite3ext_value_bytes, sqlite3ext_value_double,
-    sqlite3ext_value_int, sqlite3ext_value_int64, sqlite3ext_value_pointer,
-    sqlite3ext_value_subtype, sqlite3ext_value_text, sqlite3ext_value_type,
+    sqlite3, sqlite3_context, sqlite3_stmt, sqlite3_value, sqlite3ext_column_count,
+    sqlite3ext_column_value, sqlite3ext_context_db_handle, sqlite3ext_errmsg, sqlite3ext_exec,
+    sqlite3ext_finalize, sqlite3ext_free, sqlite3ext_get_auxdata, sqlite3ext_libversion_number,
+    sqlite3ext_mprintf, sqlite3ext_overload_function, sqlite3ext_prepare_v2,
+    sqlite3ext_result_blob, sqlite3ext_result_double, sqlite3ext_result_error,
+    sqlite3ext_result_error_code, sqlite3ext_result_int, sqlite3ext_result_int64,
+    sqlite3ext_result_null, sqlite3ext_result_pointer, sqlite3ext_result_subtype,
+    sqlite3ext_result_text, sqlite3ext_set_auxdata, sqlite3ext_step, sqlite3ext_value_blob,
+    sqlite3ext_value_bytes, sqlite3ext_value_double, sqlite3ext_value_int, sqlite3ext_value_int64,
+    sqlite3ext_value_nochange, sqlite3ext_value_pointer, sqlite3ext_value_subtype,
+    sqlite3ext_value_text, sqlite3ext_value_type, sqlite3ext_vtab_nochange,
 };
-use crate::Error;
 use sqlite3ext_sys::{SQLITE_BLOB, SQLITE_FLOAT, SQLITE_INTEGER, SQLITE_NULL, SQLITE_TEXT};
+use std::marker::PhantomData;
 use std::os::raw::c_int;
 use std::slice::from_raw_parts;
 use std::str::Utf8Error;
@@ -28,31 +32,55 @@ use std::{
 
 /// Ergonomic wrapper around a raw sqlite3_value. It is the caller's reponsibility
 /// to ensure that a given pointer points to a valid sqlite3_value object.
+/// The `'a` lifetime is borrowed from wherever the pointer came from, like a
+/// callback's `argv`, so a Value can't be kept after that callback returns.
 /// There seems to be a 5-10% perf cost when using Value vs calling functions on
 /// raw pointers
-pub struct Value {
+#[derive(Debug)]
+pub struct Value<'a> {
     value: *mut sqlite3_value,
     value_type: ValueType,
+    _marker: PhantomData<&'a sqlite3_value>,
 }
 
-impl Value {
+impl<'a> Value<'a> {
     /// Create a Value struct from a borrowed sqlite3_value pointer
-    pub fn from(value: &*mut sqlite3_value) -> crate::Result<Value> {
-        let value_type = value_type(value);
-        Ok(Value {
-            value: value.to_owned(),
-            value_type,
-        })
+    pub fn from(value: &'a *mut sqlite3_value) -> crate::Result<Value<'a>> {
+        Ok(Value::from_ptr(*value))
     }
     /// Create a Value struct from a sqlite3_value pointer slice
     /// at the given index.
-    pub fn at(values: &[*mut sqlite3_value], at: usize) -> Option<Value> {
-        let value = values.get(at)?;
-        let value_type = value_type(value);
-        Some(Value {
-            value: value.to_owned(),
-            value_type,
-        })
+    pub fn at(values: &'a [*mut sqlite3_value], at: usize) -> Option<Value<'a>> {
+        values.get(at).map(|value| Value::from_ptr(*value))
+    }
+
+    /// Wraps a pointer that stays valid for `'a`, which the caller picks.
+    pub(crate) fn from_ptr(value: *mut sqlite3_value) -> Value<'a> {
+        Value {
+            value,
+            value_type: value_type(&value),
+            _marker: PhantomData,
+        }
+    }
+
+    /// The underlying sqlite3_value pointer.
+    pub fn as_ptr(&self) -> *mut sqlite3_value {
+        self.value
+    }
+
+    /// The datatype of the value, when it was wrapped.
+    pub fn value_type(&self) -> ValueType {
+        self.value_type
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.value_type == ValueType::Null
+    }
+
+    /// Converts the value into any [`FromSqlValue`](crate::FromSqlValue) type,
+    /// ex `value.get::<i64>()` or `value.get::<Option<String>>()`.
+    pub fn get<'b, T: crate::FromSqlValue<'b>>(&'b self) -> crate::Result<T> {
+        T::from_sql_value(&self.value)
     }
 
     /// Ensure that the value's type isn't SQLITE_NULL - return the
@@ -100,7 +128,8 @@ pub enum MprintfError {
 }
 
 /// Calls [`sqlite3_mprintf`](https://sqlite.org/c3ref/mprintf.html) on the
-/// given string, with memory allocated by sqlite3.
+/// given string, with memory allocated by sqlite3. The string is copied with a
+/// `%s` format, so any `%` characters inside of it are kept as-is.
 /// Meant to be passed into sqlite APIs that require sqlite-allocated strings,
 /// like virtual table's `zErrMsg` or xBestIndex's `idxStr`
 pub fn mprintf(base: &str) -> Result<*mut c_char, MprintfError> {
@@ -192,7 +221,7 @@ pub fn value_json(value: &*mut sqlite3_value) -> serde_json::Result<serde_json::
 }
 
 /// Possible values that sqlite3_value_type will return for a value.
-#[derive(Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum ValueType {
     /// text or a string, aka SQLITE_TEXT
     Text,
@@ -206,6 +235,20 @@ pub enum ValueType {
     Null,
 }
 
+impl std::fmt::Display for ValueType {
+    /// Same names as SQL's `typeof()` function.
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            ValueType::Text => "text",
+            ValueType::Integer => "integer",
+            ValueType::Float => "real",
+            ValueType::Blob => "blob",
+            ValueType::Null => "null",
+        };
+        f.write_str(name)
+    }
+}
+
 /// Returns the [`sqlite3_value_type`](https://www.sqlite.org/c3ref/value_blob.html)
 /// result of the given value, one of TEXT/INT/FLOAT/BLOB/NULL.
 pub fn value_type(value: &*mut sqlite3_value) -> ValueType {
@@ -232,6 +275,22 @@ pub fn value_subtype(value: &*mut sqlite3_value) -> u32 {
     unsafe { sqlite3ext_value_subtype(value.to_owned()) }
 }
 
+/// Calls [`sqlite3_value_nochange`](https://www.sqlite.org/c3ref/value_blob.html).
+/// Only meaningful for the column values given to a virtual table's xUpdate:
+/// true when the UPDATE doesn't change that column, and xColumn skipped it
+/// after [`vtab_nochange`].
+pub fn value_nochange(value: &*mut sqlite3_value) -> bool {
+    unsafe { sqlite3ext_value_nochange(value.to_owned()) != 0 }
+}
+
+/// Calls [`sqlite3_vtab_nochange`](https://www.sqlite.org/c3ref/vtab_nochange.html).
+/// Returns true inside a virtual table's xColumn when the column is only read
+/// for an UPDATE that doesn't change it. xColumn can then leave the result unset,
+/// to skip computing an expensive value, and xUpdate gets an "unchanged" value instead.
+pub fn vtab_nochange(context: *mut sqlite3_context) -> bool {
+    unsafe { sqlite3ext_vtab_nochange(context) != 0 }
+}
+
 // TODO test
 pub fn value_has_pointer_subtype(value: &*mut sqlite3_value) -> bool {
     // https://github.com/sqlite/sqlite/blob/cc19bed8b10f4584d39aeb3e72fb6c30c3355955/src/vdbemem.c#L957
@@ -391,9 +450,94 @@ pub fn auxdata_get(context: *mut sqlite3_context, col: i32) -> *mut c_void {
     unsafe { sqlite3ext_get_auxdata(context, col) }
 }
 
+/// The version of the SQLite library that loaded the extension, ex `3044000`
+/// for 3.44.0, from [`sqlite3_libversion_number`](https://www.sqlite.org/c3ref/libversion.html).
+/// Can differ from the version the extension was compiled against.
+pub fn libversion_number() -> i32 {
+    unsafe { sqlite3ext_libversion_number() }
+}
+
 pub fn context_db_handle(context: *mut sqlite3_context) -> *mut sqlite3 {
     unsafe { sqlite3ext_context_db_handle(context) }
 }
+
+/// Runs one or more SQL statements that don't return rows, with
+/// [`sqlite3_exec`](https://www.sqlite.org/c3ref/exec.html). Fails with the
+/// statement's SQLite error code and message.
+pub fn exec(db: *mut sqlite3, sql: &str) -> crate::Result<()> {
+    let c_sql = CString::new(sql)?;
+    let mut errmsg: *mut c_char = std::ptr::null_mut();
+    let rc = unsafe { sqlite3ext_exec(db, c_sql.as_ptr(), &mut errmsg) };
+    if rc == SQLITE_OKAY {
+        return Ok(());
+    }
+    let message = if errmsg.is_null() {
+        None
+    } else {
+        let message = unsafe { CStr::from_ptr(errmsg) }
+            .to_string_lossy()
+            .into_owned();
+        unsafe { sqlite3ext_free(errmsg.cast::<c_void>()) };
+        Some(message)
+    };
+    Err(Error::new(ErrorKind::Sqlite { code: rc, message }))
+}
+
+/// The SQLite error code and [`sqlite3_errmsg`](https://www.sqlite.org/c3ref/errcode.html)
+/// of the given connection, as an [`Error`].
+pub(crate) fn db_error(db: *mut sqlite3, code: c_int) -> Error {
+    let errmsg = unsafe { sqlite3ext_errmsg(db) };
+    let message = if errmsg.is_null() {
+        None
+    } else {
+        Some(
+            unsafe { CStr::from_ptr(errmsg) }
+                .to_string_lossy()
+                .into_owned(),
+        )
+    };
+    Error::new(ErrorKind::Sqlite { code, message })
+}
+
+/// Runs a single SQL statement and calls `f` on every row it returns,
+/// collecting the results. The [`Value`]s given to `f` are only valid for
+/// that call, so copy out anything that's needed afterwards.
+pub fn query<T, F>(db: *mut sqlite3, sql: &str, mut f: F) -> crate::Result<Vec<T>>
+where
+    F: FnMut(&[Value]) -> crate::Result<T>,
+{
+    struct Statement(*mut sqlite3_stmt);
+    impl Drop for Statement {
+        fn drop(&mut self) {
+            unsafe { sqlite3ext_finalize(self.0) };
+        }
+    }
+
+    let c_sql = CString::new(sql)?;
+    let mut stmt: *mut sqlite3_stmt = std::ptr::null_mut();
+    let rc =
+        unsafe { sqlite3ext_prepare_v2(db, c_sql.as_ptr(), -1, &mut stmt, std::ptr::null_mut()) };
+    let stmt = Statement(stmt);
+    if rc != SQLITE_OKAY {
+        return Err(db_error(db, rc));
+    }
+
+    let mut rows = vec![];
+    loop {
+        match unsafe { sqlite3ext_step(stmt.0) } {
+            SQLITE_ROW => {
+                let count = unsafe { sqlite3ext_column_count(stmt.0) };
+                let values = (0..count)
+                    .map(|i| Value::from_ptr(unsafe { sqlite3ext_column_value(stmt.0, i) }))
+                    .collect::<Vec<_>>();
+                rows.push(f(&values)?);
+            }
+            SQLITE_DONE => return Ok(rows),
+            rc => return Err(db_error(db, rc)),
+        }
+    }
+}
+
 pub fn overload_function(db: *mut sqlite3, func_name: &str, n_args: i32) -> crate::Result<()> {
     let cname = CString::new(func_name)?;
     let result = unsafe { sqlite3ext_overload_function(db, cname.as_ptr(), n_args) };
@@ -3,17 +3,21 @@
 #![allow(clippy::not_unsafe_ptr_arg_deref)]
 use crate::{
     constants::SQLITE_OKAY,
-    errors::{Error, ErrorKind, Result},
+    errors::{catch_panic, Error, ErrorKind, Result},
     ext::{sqlite3, sqlite3ext_collation_v2},
 };
 use std::{ffi::CString, os::raw::c_void};
 
 use sqlite3ext_sys::SQLITE_UTF8;
 
+/// Defines a new collation sequence on the given database connection, with
+/// [`sqlite3_create_collation_v2`](https://www.sqlite.org/c3ref/create_collation.html).
+/// `x_func` is dropped when the collation is redefined or the connection is closed.
 pub fn define_collation<F>(db: *mut sqlite3, name: &str, x_func: F) -> Result<()>
 where
     F: Fn(&[u8], &[u8]) -> i32,
 {
+    let cname = CString::new(name)?;
     let function_pointer: *mut F = Box::into_raw(Box::new(x_func));
 
     unsafe extern "C" fn compare_function_wrapper<F>(
@@ -29,9 +33,13 @@ where
         let boxed_function: *mut F = func.cast::<F>();
         let a = std::slice::from_raw_parts(a_pointer as *const u8, a_size as usize);
         let b = std::slice::from_raw_parts(b_pointer as *const u8, b_size as usize);
-        (*boxed_function)(a, b)
+        // collations have no way to report errors, so a panic compares as equal
+        catch_panic(|| (*boxed_function)(a, b)).unwrap_or(0)
     }
-    let cname = CString::new(name)?;
+    unsafe extern "C" fn destroy<F>(func: *mut c_void) {
+        drop(Box::from_raw(func.cast::<F>()));
+    }
+
     let result = unsafe {
         sqlite3ext_collation_v2(
             db,
@@ -39,11 +47,14 @@ where
             SQLITE_UTF8 as i32,
             function_pointer.cast::<c_void>(),
             Some(compare_function_wrapper::<F>),
-            None,
+            Some(destroy::<F>),
         )
     };
 
     if result != SQLITE_OKAY {
+        // "The xDestroy callback is not called if the sqlite3_create_collation_v2()
+        // function fails", so the closure has to be dropped here instead.
+        unsafe { destroy::<F>(function_pointer.cast::<c_void>()) };
         Err(Error::new(ErrorKind::DefineScalarFunction(result)))
     } else {
         Ok(())
@@ -1,6 +1,6 @@
-/// rust bindgen for some reason is defining many SQLite constants
-/// as u32, which can't safely be casted into i32. So, here we
-/// hardcode some of those codes to avoid unwrapping
+// rust bindgen for some reason is defining many SQLite constants
+// as u32, which can't safely be casted into i32. So, here we
+// hardcode some of those codes to avoid unwrapping
 
 /// https://www.sqlite.org/rescode.html#ok
 pub const SQLITE_OKAY: i32 = 0;
@@ -8,6 +8,60 @@ pub const SQLITE_OKAY: i32 = 0;
 /// https://www.sqlite.org/rescode.html#internal
 pub const SQLITE_INTERNAL: i32 = 2;
 
+/// https://www.sqlite.org/rescode.html#perm
+pub const SQLITE_PERM: i32 = 3;
+
+/// https://www.sqlite.org/rescode.html#abort
+pub const SQLITE_ABORT: i32 = 4;
+
+/// https://www.sqlite.org/rescode.html#busy
+pub const SQLITE_BUSY: i32 = 5;
+
+/// https://www.sqlite.org/rescode.html#locked
+pub const SQLITE_LOCKED: i32 = 6;
+
+/// https://www.sqlite.org/rescode.html#nomem
+pub const SQLITE_NOMEM: i32 = 7;
+
+/// https://www.sqlite.org/rescode.html#readonly
+pub const SQLITE_READONLY: i32 = 8;
+
+/// https://www.sqlite.org/rescode.html#interrupt
+pub const SQLITE_INTERRUPT: i32 = 9;
+
+/// https://www.sqlite.org/rescode.html#ioerr
+pub const SQLITE_IOERR: i32 = 10;
+
+/// https://www.sqlite.org/rescode.html#corrupt
+pub const SQLITE_CORRUPT: i32 = 11;
+
+/// https://www.sqlite.org/rescode.html#notfound
+pub const SQLITE_NOTFOUND: i32 = 12;
+
+/// https://www.sqlite.org/rescode.html#full
+pub const SQLITE_FULL: i32 = 13;
+
+/// https://www.sqlite.org/rescode.html#cantopen
+pub const SQLITE_CANTOPEN: i32 = 14;
+
+/// https://www.sqlite.org/rescode.html#schema
+pub const SQLITE_SCHEMA: i32 = 17;
+
+/// https://www.sqlite.org/rescode.html#toobig
+pub const SQLITE_TOOBIG: i32 = 18;
+
+/// https://www.sqlite.org/rescode.html#mismatch
+pub const SQLITE_MISMATCH: i32 = 20;
+
+/// https://www.sqlite.org/rescode.html#misuse
+pub const SQLITE_MISUSE: i32 = 21;
+
+/// https://www.sqlite.org/rescode.html#auth
+pub const SQLITE_AUTH: i32 = 23;
+
+/// https://www.sqlite.org/rescode.html#range
+pub const SQLITE_RANGE: i32 = 25;
+
 /// https://www.sqlite.org/rescode.html#row
 pub const SQLITE_ROW: i32 = 100;
 
@@ -19,3 +73,30 @@ pub const SQLITE_ERROR: i32 = 1;
 
 /// https://www.sqlite.org/rescode.html#constraint
 pub const SQLITE_CONSTRAINT: i32 = 19;
+
+/// https://www.sqlite.org/rescode.html#constraint_check
+pub const SQLITE_CONSTRAINT_CHECK: i32 = SQLITE_CONSTRAINT | (1 << 8);
+
+/// https://www.sqlite.org/rescode.html#constraint_foreignkey
+pub const SQLITE_CONSTRAINT_FOREIGNKEY: i32 = SQLITE_CONSTRAINT | (3 << 8);
+
+/// https://www.sqlite.org/rescode.html#constraint_function
+pub const SQLITE_CONSTRAINT_FUNCTION: i32 = SQLITE_CONSTRAINT | (4 << 8);
+
+/// https://www.sqlite.org/rescode.html#constraint_notnull
+pub const SQLITE_CONSTRAINT_NOTNULL: i32 = SQLITE_CONSTRAINT | (5 << 8);
+
+/// https://www.sqlite.org/rescode.html#constraint_primarykey
+pub const SQLITE_CONSTRAINT_PRIMARYKEY: i32 = SQLITE_CONSTRAINT | (6 << 8);
+
+/// https://www.sqlite.org/rescode.html#constraint_unique
+pub const SQLITE_CONSTRAINT_UNIQUE: i32 = SQLITE_CONSTRAINT | (8 << 8);
+
+/// https://www.sqlite.org/rescode.html#constraint_vtab
+pub const SQLITE_CONSTRAINT_VTAB: i32 = SQLITE_CONSTRAINT | (9 << 8);
+
+/// https://www.sqlite.org/rescode.html#constraint_rowid
+pub const SQLITE_CONSTRAINT_ROWID: i32 = SQLITE_CONSTRAINT | (10 << 8);
+
+/// https://www.sqlite.org/rescode.html#constraint_datatype
+pub const SQLITE_CONSTRAINT_DATATYPE: i32 = SQLITE_CONSTRAINT | (12 << 8);
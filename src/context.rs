@@ -0,0 +1,197 @@
+//! A safe wrapper around `sqlite3_context`, the object that scalar, aggregate
+//! and window function callbacks write their results to.
+//!
+//! [`VTabCursor::column`](crate::table::VTabCursor::column) still takes the raw
+//! pointer. Virtual table cursors can implement
+//! [`VTabTypedCursor`](crate::table::VTabTypedCursor) instead, which doesn't
+//! need a context at all.
+
+use std::{
+    any::{Any, TypeId},
+    marker::PhantomData,
+    mem,
+    os::raw::c_void,
+    ptr,
+};
+
+use crate::{
+    api,
+    errors::Result,
+    ext::{sqlite3, sqlite3_context, sqlite3ext_user_data},
+    types::ToSqlResult,
+};
+
+/// Wraps the [`sqlite3_context`](https://www.sqlite.org/c3ref/context.html) pointer
+/// that SQLite passes into a function callback. The `'a` lifetime is tied to the
+/// callback itself, so a `Context` can't be stored and written to after the callback
+/// returns.
+///
+/// All the `result_*` methods are equivalent to the free functions in [`crate::api`],
+/// which still work on the raw pointer from [`Context::as_ptr`].
+pub struct Context<'a> {
+    context: *mut sqlite3_context,
+    phantom: PhantomData<&'a mut sqlite3_context>,
+}
+
+impl<'a> Context<'a> {
+    /// Wraps a raw `sqlite3_context` pointer.
+    ///
+    /// # Safety
+    /// `context` must be a valid pointer that SQLite passed into the current callback,
+    /// and the returned `Context` must not outlive that callback.
+    pub unsafe fn from_raw(context: *mut sqlite3_context) -> Self {
+        Context {
+            context,
+            phantom: PhantomData,
+        }
+    }
+
+    /// The underlying raw `sqlite3_context` pointer, for use with [`crate::api`].
+    pub fn as_ptr(&self) -> *mut sqlite3_context {
+        self.context
+    }
+
+    /// Results any value that implements [`ToSqlResult`].
+    pub fn result<T: ToSqlResult>(&mut self, value: T) -> Result<()> {
+        value.to_sql_result(self.context)
+    }
+
+    /// See [`api::result_text`].
+    pub fn result_text<S: AsRef<str>>(&mut self, text: S) -> Result<()> {
+        api::result_text(self.context, text)
+    }
+
+    /// See [`api::result_int`].
+    pub fn result_int(&mut self, i: i32) {
+        api::result_int(self.context, i)
+    }
+
+    /// See [`api::result_int64`].
+    pub fn result_int64(&mut self, i: i64) {
+        api::result_int64(self.context, i)
+    }
+
+    /// See [`api::result_double`].
+    pub fn result_double(&mut self, f: f64) {
+        api::result_double(self.context, f)
+    }
+
+    /// See [`api::result_bool`].
+    pub fn result_bool(&mut self, value: bool) {
+        api::result_bool(self.context, value)
+    }
+
+    /// See [`api::result_blob`].
+    pub fn result_blob(&mut self, blob: &[u8]) {
+        api::result_blob(self.context, blob)
+    }
+
+    /// See [`api::result_null`].
+    pub fn result_null(&mut self) {
+        api::result_null(self.context)
+    }
+
+    /// See [`api::result_json`].
+    pub fn result_json(&mut self, value: serde_json::Value) -> Result<()> {
+        api::result_json(self.context, value)
+    }
+
+    /// See [`api::result_subtype`].
+    pub fn result_subtype(&mut self, subtype: u8) {
+        api::result_subtype(self.context, subtype)
+    }
+
+    /// See [`api::result_pointer`]. `name` must be a NUL-terminated byte string,
+    /// like `b"my_pointer\0"`.
+    pub fn result_pointer<T>(&mut self, name: &'static [u8], object: T) {
+        api::result_pointer(self.context, name, object)
+    }
+
+    /// See [`api::result_error`]. Usually returning an `Err` from the callback
+    /// is simpler.
+    pub fn result_error(&mut self, message: &str) -> Result<()> {
+        api::result_error(self.context, message)
+    }
+
+    /// Caches `data` as the auxiliary data for the `arg`-th argument, with
+    /// [`sqlite3_set_auxdata`](https://www.sqlite.org/c3ref/get_auxdata.html).
+    /// SQLite drops the data whenever it wants, typically when the argument
+    /// value changes or the statement is finalized.
+    pub fn set_auxdata<T: Any>(&mut self, arg: i32, data: T) {
+        let boxed = Box::new(AuxData {
+            header: AuxDataHeader::of::<T>(),
+            data,
+        });
+        let pointer = Box::into_raw(boxed).cast::<c_void>();
+        api::auxdata_set(self.context, arg, pointer, Some(auxdata_destroy::<T>));
+    }
+
+    /// Returns the auxiliary data for the `arg`-th argument that a previous call
+    /// of this function stored with [`Context::set_auxdata`]. Returns `None` if
+    /// there is no data, if it isn't a `T`, or if it was stored some other way,
+    /// like with [`api::auxdata_set`]. Those pointers must point to at least 32
+    /// readable bytes, since the header that tells them apart is read first.
+    pub fn get_auxdata<T: Any>(&self, arg: i32) -> Option<&T> {
+        let data = api::auxdata_get(self.context, arg);
+        if data.is_null() {
+            return None;
+        }
+        let header = unsafe { ptr::read_unaligned(data.cast::<AuxDataHeader>()) };
+        if header != AuxDataHeader::of::<T>() {
+            return None;
+        }
+        // set_auxdata() wrote this header, so this is an AuxData<T> that SQLite
+        // won't destroy before the callback returns
+        Some(unsafe { &(*data.cast::<AuxData<T>>()).data })
+    }
+
+    /// The database connection that the function is running on, from
+    /// [`sqlite3_context_db_handle`](https://www.sqlite.org/c3ref/context_db_handle.html).
+    pub fn db_handle(&self) -> *mut sqlite3 {
+        api::context_db_handle(self.context)
+    }
+
+    /// The application data pointer the function was registered with, from
+    /// [`sqlite3_user_data`](https://www.sqlite.org/c3ref/user_data.html).
+    /// Functions defined with this crate's `define_*` functions store their own
+    /// internal state here, so this is mostly useful for functions that were
+    /// registered with the raw C API.
+    pub fn user_data(&self) -> *mut c_void {
+        unsafe { sqlite3ext_user_data(self.context) }
+    }
+}
+
+/// "sqlt_aux", at the start of every auxdata created by [`Context::set_auxdata`].
+const AUXDATA_MAGIC: u64 = u64::from_be_bytes(*b"sqlt_aux");
+
+/// Written before the data by [`Context::set_auxdata`], so that
+/// [`Context::get_auxdata`] never casts a pointer that came from somewhere else,
+/// or that holds another type.
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+struct AuxDataHeader {
+    magic: u64,
+    type_id: TypeId,
+}
+
+// the size that get_auxdata() documents for other auxdata pointers
+const _: () = assert!(mem::size_of::<AuxDataHeader>() <= 32);
+
+impl AuxDataHeader {
+    fn of<T: Any>() -> Self {
+        AuxDataHeader {
+            magic: AUXDATA_MAGIC,
+            type_id: TypeId::of::<T>(),
+        }
+    }
+}
+
+#[repr(C)]
+struct AuxData<T> {
+    header: AuxDataHeader,
+    data: T,
+}
+
+unsafe extern "C" fn auxdata_destroy<T>(p: *mut c_void) {
+    drop(Box::from_raw(p.cast::<AuxData<T>>()));
+}
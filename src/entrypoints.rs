@@ -1,7 +1,8 @@
 //! Utilities for working with SQLite's "sqlite3_extension_init"-style
 //! entrypoints.
 use crate::{
-    errors::Result,
+    api::mprintf,
+    errors::{catch_panic, Error, Result},
     ext::{faux_sqlite_extension_init2, sqlite3, sqlite3_api_routines},
 };
 
@@ -9,12 +10,24 @@ use sqlite3ext_sys::SQLITE_OK;
 
 use std::os::raw::{c_char, c_uint};
 
+/// Hands the error message to SQLite through `pz_err_msg`, which SQLite frees,
+/// and returns the (extended) result code of the error.
+fn report_error(pz_err_msg: *mut *mut c_char, err: Error) -> c_uint {
+    let code = err.code_extended();
+    if !pz_err_msg.is_null() {
+        if let Ok(msg) = mprintf(&err.result_error_message()) {
+            unsafe { *pz_err_msg = msg };
+        }
+    }
+    code
+}
+
 /// Low-level wrapper around a typical entrypoint to a SQLite extension.
 /// You shouldn't have to use this directly - the sqlite_entrypoint
 /// macro will do this for you.
 pub fn register_entrypoint<F>(
     db: *mut sqlite3,
-    _pz_err_msg: *mut *mut c_char,
+    pz_err_msg: *mut *mut c_char,
     p_api: *mut sqlite3_api_routines,
     callback: F,
 ) -> c_uint
@@ -24,9 +37,9 @@ where
     unsafe {
         faux_sqlite_extension_init2(p_api);
     }
-    match callback(db) {
+    match catch_panic(|| callback(db)).and_then(|result| result) {
         Ok(()) => SQLITE_OK,
-        Err(err) => err.code_extended(),
+        Err(err) => report_error(pz_err_msg, err),
     }
 }
 
@@ -35,7 +48,7 @@ where
 /// for you.
 pub fn register_entrypoint_load_permanently<F>(
     db: *mut sqlite3,
-    _pz_err_msg: *mut *mut c_char,
+    pz_err_msg: *mut *mut c_char,
     p_api: *mut sqlite3_api_routines,
     callback: F,
 ) -> c_uint
@@ -45,8 +58,8 @@ where
     unsafe {
         faux_sqlite_extension_init2(p_api);
     }
-    match callback(db) {
+    match catch_panic(|| callback(db)).and_then(|result| result) {
         Ok(()) => 256, // https://www.sqlite.org/rescode.html#ok_load_permanently
-        Err(err) => err.code_extended(),
+        Err(err) => report_error(pz_err_msg, err),
     }
 }
@@ -1,11 +1,16 @@
 //! Custom Error/Result for sqlite-loadable-rs APIs.
 use std::{
+    any::Any,
+    error,
     ffi::NulError,
     fmt,
     os::raw::{c_int, c_uint},
+    panic::{self, AssertUnwindSafe},
     result,
 };
 
+use crate::constants::*;
+
 /// A type alias for `Result<T, xxx::Error>`.
 pub type Result<T> = result::Result<T, Error>;
 
@@ -22,6 +27,25 @@ impl Error {
         Error(Box::new(ErrorKind::Message(message.as_ref().to_owned())))
     }
 
+    /// An error with a specific SQLite result code, which can be a primary code like
+    /// [`SQLITE_TOOBIG`] or an extended code like [`SQLITE_CONSTRAINT_UNIQUE`].
+    /// <https://www.sqlite.org/rescode.html>
+    pub fn new_code<S: AsRef<str>>(code: c_int, message: S) -> Error {
+        Error::new(ErrorKind::Sqlite {
+            code,
+            message: Some(message.as_ref().to_owned()),
+        })
+    }
+
+    /// Wraps any other error type, which is then available from
+    /// [`std::error::Error::source`].
+    pub fn external<E>(err: E) -> Error
+    where
+        E: error::Error + Send + Sync + 'static,
+    {
+        Error::new(ErrorKind::External(Box::new(err)))
+    }
+
     /// Return the specific type of this error.
     pub fn kind(&self) -> &ErrorKind {
         &self.0
@@ -32,33 +56,75 @@ impl Error {
         *self.0
     }
 
-    pub fn code(self) -> c_int {
-        1
+    /// The primary SQLite result code for this error, ie the lower 8 bits of
+    /// [`Error::code_extended`].
+    pub fn code(&self) -> c_int {
+        self.code_extended() as c_int & 0xff
     }
-    pub fn code_extended(self) -> c_uint {
-        1
+
+    /// The (possibly extended) SQLite result code for this error.
+    /// <https://www.sqlite.org/rescode.html#primary_result_codes_versus_extended_result_codes>
+    pub fn code_extended(&self) -> c_uint {
+        let code = match *self.0 {
+            ErrorKind::DefineScalarFunction(code) | ErrorKind::TableFunction(code) => code,
+            ErrorKind::CStringError(_) | ErrorKind::CStringUtf8Error(_) => SQLITE_ERROR,
+            ErrorKind::Message(_) | ErrorKind::External(_) => SQLITE_ERROR,
+            ErrorKind::Panic(_) => SQLITE_INTERNAL,
+            ErrorKind::Sqlite { code, .. } => code,
+        };
+        code as c_uint
     }
+
     pub fn result_error_message(self) -> String {
-        match *self.0 {
-            ErrorKind::DefineScalarFunction(_) => "Error defining scalar function".to_owned(),
-            ErrorKind::CStringError(e) => format!("String Nul error: {}", e),
-            ErrorKind::CStringUtf8Error(_) => "utf8 err".to_owned(),
-            ErrorKind::Message(msg) => msg,
-            ErrorKind::TableFunction(_) => "table func error".to_owned(),
-        }
+        self.to_string()
     }
 }
 
 /// The specific type of an error.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug)]
 pub enum ErrorKind {
     DefineScalarFunction(c_int),
     CStringError(NulError),
     CStringUtf8Error(std::str::Utf8Error),
     TableFunction(c_int),
     Message(String),
+    /// User code panicked inside of a callback that SQLite called, with the panic message.
+    Panic(String),
+    /// An error with a specific SQLite result code. When `message` is None, SQLite's
+    /// default description of the code is used instead.
+    Sqlite {
+        code: c_int,
+        message: Option<String>,
+    },
+    /// Any other error, see [`Error::external`].
+    External(Box<dyn error::Error + Send + Sync + 'static>),
 }
 
+impl PartialEq for ErrorKind {
+    fn eq(&self, other: &ErrorKind) -> bool {
+        match (self, other) {
+            (ErrorKind::DefineScalarFunction(a), ErrorKind::DefineScalarFunction(b)) => a == b,
+            (ErrorKind::CStringError(a), ErrorKind::CStringError(b)) => a == b,
+            (ErrorKind::CStringUtf8Error(a), ErrorKind::CStringUtf8Error(b)) => a == b,
+            (ErrorKind::TableFunction(a), ErrorKind::TableFunction(b)) => a == b,
+            (ErrorKind::Message(a), ErrorKind::Message(b)) => a == b,
+            (ErrorKind::Panic(a), ErrorKind::Panic(b)) => a == b,
+            (
+                ErrorKind::Sqlite { code, message },
+                ErrorKind::Sqlite {
+                    code: other_code,
+                    message: other_message,
+                },
+            ) => code == other_code && message == other_message,
+            // boxed errors can't be compared, so their messages are instead
+            (ErrorKind::External(a), ErrorKind::External(b)) => a.to_string() == b.to_string(),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for ErrorKind {}
+
 impl From<NulError> for Error {
     fn from(err: NulError) -> Error {
         Error::new(ErrorKind::CStringError(err))
@@ -82,13 +148,141 @@ impl From<String> for Error {
     }
 }
 
+impl From<Box<dyn error::Error + Send + Sync + 'static>> for Error {
+    fn from(err: Box<dyn error::Error + Send + Sync + 'static>) -> Error {
+        Error::new(ErrorKind::External(err))
+    }
+}
+
 impl ErrorKind {}
 
+/// Calls `f`, catching any panic so it doesn't unwind into SQLite's C stack frames,
+/// which is undefined behavior. A panic is returned as an [`ErrorKind::Panic`] error.
+///
+/// With the `abort_on_panic` feature, the process is aborted instead.
+pub(crate) fn catch_panic<T, F: FnOnce() -> T>(f: F) -> result::Result<T, Error> {
+    // A panic is treated like an early error return: whatever state the callback
+    // touched is left as-is, same as when it returns an Err halfway through.
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(|payload| {
+        if cfg!(feature = "abort_on_panic") {
+            std::process::abort();
+        }
+        Error::new(ErrorKind::Panic(panic_message(payload)))
+    })
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        (*msg).to_owned()
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        "Rust panic with a non-string payload".to_owned()
+    }
+}
+
+/// Same descriptions as [`sqlite3_errstr`](https://www.sqlite.org/c3ref/errcode.html),
+/// for the result codes that an extension is likely to return.
+fn code_description(code: c_int) -> &'static str {
+    match code & 0xff {
+        SQLITE_ERROR => "SQL logic error",
+        SQLITE_INTERNAL => "internal error",
+        SQLITE_PERM => "access permission denied",
+        SQLITE_ABORT => "query aborted",
+        SQLITE_BUSY => "database is locked",
+        SQLITE_LOCKED => "database table is locked",
+        SQLITE_NOMEM => "out of memory",
+        SQLITE_READONLY => "attempt to write a readonly database",
+        SQLITE_INTERRUPT => "interrupted",
+        SQLITE_IOERR => "disk I/O error",
+        SQLITE_CORRUPT => "database disk image is malformed",
+        SQLITE_NOTFOUND => "unknown operation",
+        SQLITE_FULL => "database or disk is full",
+        SQLITE_CANTOPEN => "unable to open database file",
+        SQLITE_SCHEMA => "database schema has changed",
+        SQLITE_TOOBIG => "string or blob too big",
+        SQLITE_CONSTRAINT => "constraint failed",
+        SQLITE_MISMATCH => "datatype mismatch",
+        SQLITE_MISUSE => "bad parameter or other API misuse",
+        SQLITE_AUTH => "authorization denied",
+        SQLITE_RANGE => "column index out of range",
+        _ => "unknown error",
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self.0 {
-            ErrorKind::DefineScalarFunction(ref err) => err.fmt(f),
-            _ => unreachable!(),
+            ErrorKind::DefineScalarFunction(code) => write!(
+                f,
+                "Error defining scalar function: {}",
+                code_description(code)
+            ),
+            ErrorKind::CStringError(ref err) => write!(f, "String Nul error: {}", err),
+            ErrorKind::CStringUtf8Error(ref err) => write!(f, "UTF-8 error: {}", err),
+            ErrorKind::TableFunction(code) => write!(
+                f,
+                "Error defining virtual table module: {}",
+                code_description(code)
+            ),
+            ErrorKind::Message(ref msg) | ErrorKind::Panic(ref msg) => f.write_str(msg),
+            ErrorKind::Sqlite {
+                message: Some(ref msg),
+                ..
+            } => f.write_str(msg),
+            ErrorKind::Sqlite {
+                code,
+                message: None,
+            } => f.write_str(code_description(code)),
+            ErrorKind::External(ref err) => err.fmt(f),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self.0 {
+            ErrorKind::CStringError(ref err) => Some(err),
+            ErrorKind::CStringUtf8Error(ref err) => Some(err),
+            ErrorKind::External(ref err) => Some(err.as_ref()),
+            _ => None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+
+    #[test]
+    fn test_codes() {
+        assert_eq!(Error::new_message("x").code(), SQLITE_ERROR);
+        assert_eq!(
+            Error::new(ErrorKind::Panic("x".to_owned())).code(),
+            SQLITE_INTERNAL
+        );
+
+        let err = Error::new_code(SQLITE_CONSTRAINT_UNIQUE, "duplicate");
+        assert_eq!(err.code(), SQLITE_CONSTRAINT);
+        assert_eq!(err.code_extended(), SQLITE_CONSTRAINT_UNIQUE as c_uint);
+        assert_eq!(err.to_string(), "duplicate");
+
+        let err = Error::new(ErrorKind::Sqlite {
+            code: SQLITE_TOOBIG,
+            message: None,
+        });
+        assert_eq!(err.to_string(), "string or blob too big");
+    }
+
+    #[test]
+    fn test_source() {
+        let io = std::io::Error::new(std::io::ErrorKind::NotFound, "missing.csv");
+        let err = Error::external(io);
+        assert_eq!(err.to_string(), "missing.csv");
+        assert_eq!(err.code(), SQLITE_ERROR);
+        assert_eq!(err.source().unwrap().to_string(), "missing.csv");
+
+        assert!(Error::new_message("x").source().is_none());
+    }
+}
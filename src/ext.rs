@@ -80,6 +80,24 @@ pub unsafe fn sqlite3ext_value_subtype(value: *mut sqlite3_value) -> u32 {
     ((*SQLITE3_API).value_subtype.expect(EXPECT_MESSAGE))(value)
 }
 
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_value_nochange(value: *mut sqlite3_value) -> i32 {
+    libsqlite3_sys::sqlite3_value_nochange(value)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_value_nochange(value: *mut sqlite3_value) -> i32 {
+    ((*SQLITE3_API).value_nochange.expect(EXPECT_MESSAGE))(value)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_vtab_nochange(context: *mut sqlite3_context) -> i32 {
+    libsqlite3_sys::sqlite3_vtab_nochange(context)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_vtab_nochange(context: *mut sqlite3_context) -> i32 {
+    ((*SQLITE3_API).vtab_nochange.expect(EXPECT_MESSAGE))(context)
+}
+
 #[cfg(feature = "static")]
 pub unsafe fn sqlite3ext_value_bytes(arg1: *mut sqlite3_value) -> i32 {
     libsqlite3_sys::sqlite3_value_bytes(arg1)
@@ -125,6 +143,33 @@ pub unsafe fn sqlite3ext_step(stmt: *mut sqlite3_stmt) -> c_int {
     ((*SQLITE3_API).step.expect(EXPECT_MESSAGE))(stmt)
 }
 
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_column_count(stmt: *mut sqlite3_stmt) -> c_int {
+    libsqlite3_sys::sqlite3_column_count(stmt)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_column_count(stmt: *mut sqlite3_stmt) -> c_int {
+    ((*SQLITE3_API).column_count.expect(EXPECT_MESSAGE))(stmt)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_errmsg(db: *mut sqlite3) -> *const c_char {
+    libsqlite3_sys::sqlite3_errmsg(db)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_errmsg(db: *mut sqlite3) -> *const c_char {
+    ((*SQLITE3_API).errmsg.expect(EXPECT_MESSAGE))(db)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_libversion_number() -> c_int {
+    libsqlite3_sys::sqlite3_libversion_number()
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_libversion_number() -> c_int {
+    ((*SQLITE3_API).libversion_number.expect(EXPECT_MESSAGE))()
+}
+
 #[cfg(feature = "static")]
 pub unsafe fn sqlite3ext_finalize(stmt: *mut sqlite3_stmt) -> c_int {
     libsqlite3_sys::sqlite3_finalize(stmt)
@@ -296,6 +341,24 @@ pub unsafe fn sqlite3ext_result_blob(context: *mut sqlite3_context, p: *const c_
     );
 }
 #[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_result_blob_with_destructor(
+    context: *mut sqlite3_context,
+    p: *const c_void,
+    n: i32,
+    d: Option<unsafe extern "C" fn(*mut c_void)>,
+) {
+    libsqlite3_sys::sqlite3_result_blob(context, p, n, d);
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_result_blob_with_destructor(
+    context: *mut sqlite3_context,
+    p: *const c_void,
+    n: i32,
+    d: Option<unsafe extern "C" fn(*mut c_void)>,
+) {
+    ((*SQLITE3_API).result_blob.expect(EXPECT_MESSAGE))(context, p, n, d);
+}
+#[cfg(feature = "static")]
 pub unsafe fn sqlite3ext_result_int64(context: *mut sqlite3_context, v: i64) {
     libsqlite3_sys::sqlite3_result_int64(context, v);
 }
@@ -445,6 +508,56 @@ pub unsafe fn sqlite3ext_create_function_v2(
         db, s, argc, text_rep, p_app, x_func, x_step, x_final, destroy,
     )
 }
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_create_window_function(
+    db: *mut sqlite3,
+    s: *const c_char,
+    argc: i32,
+    text_rep: i32,
+    p_app: *mut c_void,
+    x_step: Option<unsafe extern "C" fn(*mut sqlite3_context, i32, *mut *mut sqlite3_value)>,
+    x_final: Option<unsafe extern "C" fn(*mut sqlite3_context)>,
+    x_value: Option<unsafe extern "C" fn(*mut sqlite3_context)>,
+    x_inverse: Option<unsafe extern "C" fn(*mut sqlite3_context, i32, *mut *mut sqlite3_value)>,
+    destroy: Option<unsafe extern "C" fn(*mut c_void)>,
+) -> c_int {
+    libsqlite3_sys::sqlite3_create_window_function(
+        db, s, argc, text_rep, p_app, x_step, x_final, x_value, x_inverse, destroy,
+    )
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_create_window_function(
+    db: *mut sqlite3,
+    s: *const c_char,
+    argc: i32,
+    text_rep: i32,
+    p_app: *mut c_void,
+    x_step: Option<unsafe extern "C" fn(*mut sqlite3_context, i32, *mut *mut sqlite3_value)>,
+    x_final: Option<unsafe extern "C" fn(*mut sqlite3_context)>,
+    x_value: Option<unsafe extern "C" fn(*mut sqlite3_context)>,
+    x_inverse: Option<unsafe extern "C" fn(*mut sqlite3_context, i32, *mut *mut sqlite3_value)>,
+    destroy: Option<unsafe extern "C" fn(*mut c_void)>,
+) -> c_int {
+    ((*SQLITE3_API).create_window_function.expect(EXPECT_MESSAGE))(
+        db, s, argc, text_rep, p_app, x_step, x_final, x_value, x_inverse, destroy,
+    )
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_aggregate_context(
+    context: *mut sqlite3_context,
+    n_bytes: c_int,
+) -> *mut c_void {
+    libsqlite3_sys::sqlite3_aggregate_context(context, n_bytes)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_aggregate_context(
+    context: *mut sqlite3_context,
+    n_bytes: c_int,
+) -> *mut c_void {
+    ((*SQLITE3_API).aggregate_context.expect(EXPECT_MESSAGE))(context, n_bytes)
+}
+
 #[cfg(feature = "static")]
 pub unsafe fn sqlite3ext_collation_v2(
     db: *mut sqlite3,
@@ -533,6 +646,38 @@ pub unsafe fn sqlite3ext_vtab_in(
     ((*SQLITE3_API).vtab_in.expect(EXPECT_MESSAGE))(index_info, constraint_idx, handle)
 }
 
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_vtab_rhs_value(
+    index_info: *mut sqlite3_index_info,
+    constraint_idx: i32,
+    value_out: *mut *mut sqlite3_value,
+) -> i32 {
+    libsqlite3_sys::sqlite3_vtab_rhs_value(index_info, constraint_idx, value_out)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_vtab_rhs_value(
+    index_info: *mut sqlite3_index_info,
+    constraint_idx: i32,
+    value_out: *mut *mut sqlite3_value,
+) -> i32 {
+    ((*SQLITE3_API).vtab_rhs_value.expect(EXPECT_MESSAGE))(index_info, constraint_idx, value_out)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_vtab_collation(
+    index_info: *mut sqlite3_index_info,
+    constraint_idx: i32,
+) -> *const c_char {
+    libsqlite3_sys::sqlite3_vtab_collation(index_info, constraint_idx)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_vtab_collation(
+    index_info: *mut sqlite3_index_info,
+    constraint_idx: i32,
+) -> *const c_char {
+    ((*SQLITE3_API).vtab_collation.expect(EXPECT_MESSAGE))(index_info, constraint_idx)
+}
+
 #[cfg(feature = "static")]
 pub unsafe fn sqlite3ext_vtab_in_first(
     value_list: *mut sqlite3_value,
@@ -599,18 +744,72 @@ pub unsafe fn sqlite3ext_user_data(context: *mut sqlite3_context) -> *mut c_void
 pub unsafe fn sqlite3ext_user_data(context: *mut sqlite3_context) -> *mut c_void {
     ((*SQLITE3_API).user_data.expect(EXPECT_MESSAGE))(context)
 }
+/// sqlite3_exec without a callback, for statements that don't return rows.
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_exec(
+    db: *mut sqlite3,
+    sql: *const c_char,
+    errmsg: *mut *mut c_char,
+) -> c_int {
+    libsqlite3_sys::sqlite3_exec(db, sql, None, std::ptr::null_mut(), errmsg)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_exec(
+    db: *mut sqlite3,
+    sql: *const c_char,
+    errmsg: *mut *mut c_char,
+) -> c_int {
+    ((*SQLITE3_API).exec.expect(EXPECT_MESSAGE))(db, sql, None, std::ptr::null_mut(), errmsg)
+}
+
+/// `arg` is only read by SQLite for the options that take an argument, like
+/// SQLITE_VTAB_CONSTRAINT_SUPPORT.
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_vtab_config(db: *mut sqlite3, op: c_int, arg: c_int) -> c_int {
+    libsqlite3_sys::sqlite3_vtab_config(db, op, arg)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_vtab_config(db: *mut sqlite3, op: c_int, arg: c_int) -> c_int {
+    ((*SQLITE3_API).vtab_config.expect(EXPECT_MESSAGE))(db, op, arg)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_vtab_on_conflict(db: *mut sqlite3) -> c_int {
+    libsqlite3_sys::sqlite3_vtab_on_conflict(db)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_vtab_on_conflict(db: *mut sqlite3) -> c_int {
+    ((*SQLITE3_API).vtab_on_conflict.expect(EXPECT_MESSAGE))(db)
+}
+
+/// sqlite3ext_mprintf copies its argument with a `%s` format, so strings that
+/// contain `%` are never interpreted as format directives.
+const MPRINTF_STRING_FORMAT: &[u8] = b"%s\0";
+
 #[cfg(feature = "static")]
 pub unsafe fn sqlite3ext_mprintf(s: *const c_char) -> *mut c_char {
-    libsqlite3_sys::sqlite3_mprintf(s)
+    libsqlite3_sys::sqlite3_mprintf(MPRINTF_STRING_FORMAT.as_ptr().cast::<c_char>(), s)
 }
 #[cfg(not(feature = "static"))]
 pub unsafe fn sqlite3ext_mprintf(s: *const c_char) -> *mut c_char {
-    ((*SQLITE3_API).mprintf.expect(EXPECT_MESSAGE))(s)
+    ((*SQLITE3_API).mprintf.expect(EXPECT_MESSAGE))(
+        MPRINTF_STRING_FORMAT.as_ptr().cast::<c_char>(),
+        s,
+    )
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_free(p: *mut c_void) {
+    libsqlite3_sys::sqlite3_free(p)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_free(p: *mut c_void) {
+    ((*SQLITE3_API).free.expect(EXPECT_MESSAGE))(p)
 }
 
 #[cfg(feature = "static")]
 pub unsafe fn sqlite3ext_auto_extension(f: unsafe extern "C" fn()) -> i32 {
-    libsqlite3_sys::sqlite3_auto_extension(Some(f))
+    libsqlite3_sys::sqlite3_auto_extension(Some(mem::transmute(f)))
 }
 #[cfg(not(feature = "static"))]
 pub unsafe fn sqlite3ext_auto_extension(f: unsafe extern "C" fn()) -> i32 {
@@ -1,9 +1,11 @@
 #![doc = include_str!("../README.md")]
 #![allow(clippy::not_unsafe_ptr_arg_deref)]
 
+pub mod aggregate;
 pub mod api;
 pub mod collation;
 mod constants;
+pub mod context;
 pub mod entrypoints;
 pub mod errors;
 
@@ -13,13 +15,26 @@ pub mod ext; // TODO dont expose
 pub mod prelude;
 pub mod scalar;
 pub mod table;
+pub mod types;
 pub mod vtab_argparse;
 
 #[doc(inline)]
 pub use errors::{Error, ErrorKind, Result};
 
 #[doc(inline)]
-pub use scalar::{define_scalar_function, define_scalar_function_with_aux, FunctionFlags};
+pub use aggregate::{define_aggregate_function, define_window_function, Aggregate, WindowFunction};
+
+#[doc(inline)]
+pub use scalar::{
+    define_scalar_function, define_scalar_function_with_aux, define_scalar_function_with_context,
+    define_typed_scalar_function, FunctionFlags,
+};
+
+#[doc(inline)]
+pub use types::{FromSqlValue, SqlValue, ToSqlResult};
+
+#[doc(inline)]
+pub use context::Context;
 
 #[doc(inline)]
 pub use collation::define_collation;
@@ -27,7 +42,7 @@ pub use collation::define_collation;
 #[doc(inline)]
 pub use table::{
     define_table_function, define_virtual_table, define_virtual_table_with_find,
-    define_virtual_table_writeable, define_virtual_table_writeablex, BestIndexError,
+    define_virtual_table_writeable, define_virtual_table_writeablex, BestIndexError, ModuleBuilder,
 };
 
 pub use constants::*;
@@ -11,6 +11,7 @@ pub use crate::ext::{
 };
 pub use sqlite_loadable_macros::sqlite_entrypoint;
 pub use sqlite_loadable_macros::sqlite_entrypoint_permanent;
+pub use sqlite_loadable_macros::{sqlite_function, sqlite_functions};
 
 pub use std::os::raw::{c_char, c_uint};
 
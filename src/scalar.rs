@@ -3,18 +3,22 @@
 #![allow(clippy::not_unsafe_ptr_arg_deref)]
 use std::{
     ffi::CString,
+    marker::PhantomData,
+    mem,
     os::raw::{c_int, c_void},
-    slice,
+    ptr, slice,
 };
 
 use crate::{
     api,
-    constants::{SQLITE_INTERNAL, SQLITE_OKAY},
-    errors::{Error, ErrorKind, Result},
+    constants::{SQLITE_ERROR, SQLITE_INTERNAL, SQLITE_OKAY},
+    context::Context,
+    errors::{catch_panic, Error, ErrorKind, Result},
     ext::{
         sqlite3, sqlite3_context, sqlite3_value, sqlite3ext_create_function_v2,
         sqlite3ext_user_data,
     },
+    types::{FromSqlValue, ToSqlResult, TypedArgument},
 };
 
 use bitflags::bitflags;
@@ -45,7 +49,8 @@ bitflags! {
     }
 }
 
-fn create_function_v2(
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn create_function_v2(
     db: *mut sqlite3,
     name: &str,
     num_args: c_int,
@@ -56,7 +61,17 @@ fn create_function_v2(
     x_final: Option<unsafe extern "C" fn(*mut sqlite3_context)>,
     destroy: Option<unsafe extern "C" fn(*mut c_void)>,
 ) -> Result<()> {
-    let cname = CString::new(name)?;
+    let cname = match CString::new(name) {
+        Ok(cname) => cname,
+        Err(err) => {
+            // SQLite never saw p_app, so it's on us to clean it up
+            if let Some(destroy) = destroy {
+                unsafe { destroy(p_app) };
+            }
+            return Err(err.into());
+        }
+    };
+    // "If the function fails, xDestroy is invoked" - so no cleanup needed after this.
     let result = unsafe {
         sqlite3ext_create_function_v2(
             db,
@@ -97,10 +112,6 @@ pub fn define_scalar_function<F>(
     func_flags: FunctionFlags,
 ) -> Result<()>
 where
-    // TODO - can we wrap the context arg with a safe/ergonomic struct?
-    // calling `context_result_text(context, "foo")` is long, but maybe
-    // `context.result_text("foo")` with a special wrapper struct can be
-    // as fast
     F: Fn(*mut sqlite3_context, &[*mut sqlite3_value]) -> Result<()>,
 {
     let function_pointer: *mut F = Box::into_raw(Box::new(x_func));
@@ -115,13 +126,9 @@ where
         let boxed_function: *mut F = sqlite3ext_user_data(context).cast::<F>();
         // .collect slows things waaaay down, so stick with slice for now
         let args = slice::from_raw_parts(argv, argc as usize);
-        match (*boxed_function)(context, args) {
-            Ok(()) => (),
-            Err(e) => {
-                if api::result_error(context, &e.result_error_message()).is_err() {
-                    api::result_error_code(context, SQLITE_INTERNAL);
-                }
-            }
+        if let Err(err) = catch_panic(|| (*boxed_function)(context, args)).and_then(|result| result)
+        {
+            result_error(context, err);
         }
     }
     create_function_v2(
@@ -133,7 +140,170 @@ where
         Some(x_func_wrapper::<F>),
         None,
         None,
-        None,
+        Some(destroy_boxed::<F>),
+    )
+}
+
+/// Reports `err` as the result of the function call, with both its message and code.
+pub(crate) fn result_error(context: *mut sqlite3_context, err: Error) {
+    let code = err.code_extended() as c_int;
+    if api::result_error(context, &err.result_error_message()).is_err() {
+        api::result_error_code(context, SQLITE_INTERNAL);
+    } else if code != SQLITE_ERROR {
+        // keeps the message from above, only the code is changed
+        api::result_error_code(context, code);
+    }
+}
+
+/// Used as the xDestroy callback of functions whose application data is a `Box<T>`.
+/// SQLite calls it when the function is overridden, deleted, or when the
+/// connection closes.
+pub(crate) unsafe extern "C" fn destroy_boxed<T>(p: *mut c_void) {
+    drop(Box::from_raw(p.cast::<T>()));
+}
+
+/// Defines a new scalar function like [`define_scalar_function`], but the callback
+/// receives a [`Context`] instead of a raw `sqlite3_context` pointer.
+///
+/// # Example
+/// ```rust
+/// fn xyz_version(context: &mut Context, _values: &[*mut sqlite3_value]) -> Result<()> {
+///   context.result_text(format!("v{}", env!("CARGO_PKG_VERSION")))
+/// }
+///
+/// define_scalar_function_with_context(db, "xyz_version", 0, xyz_version, FunctionFlags::UTF8)?;
+/// ```
+pub fn define_scalar_function_with_context<F>(
+    db: *mut sqlite3,
+    name: &str,
+    num_args: c_int,
+    x_func: F,
+    func_flags: FunctionFlags,
+) -> Result<()>
+where
+    F: Fn(&mut Context, &[*mut sqlite3_value]) -> Result<()>,
+{
+    define_scalar_function(
+        db,
+        name,
+        num_args,
+        move |context, values| {
+            // the context only lives for this one call of the function
+            let mut context = unsafe { Context::from_raw(context) };
+            x_func(&mut context, values)
+        },
+        func_flags,
+    )
+}
+
+/// A Rust function or closure whose arguments and return value are converted from and
+/// to SQLite values, see [`define_typed_scalar_function`]. `Args` is a tuple of the
+/// argument types, and is only there so a single type can implement this for
+/// different arities. Implemented for functions with up to 8 arguments.
+pub trait TypedScalarFunction<Args> {
+    /// The number of arguments the SQL function takes.
+    const ARITY: c_int;
+
+    /// Converts the given values into Rust arguments, calls the function, and results
+    /// the returned value on the context. `name` is only used in error messages.
+    fn call(
+        &self,
+        name: &str,
+        context: *mut sqlite3_context,
+        values: &[*mut sqlite3_value],
+    ) -> Result<()>;
+}
+
+fn typed_argument<'a, T: FromSqlValue<'a>>(
+    name: &str,
+    values: &'a [*mut sqlite3_value],
+    i: usize,
+) -> Result<T> {
+    T::from_sql_value(&values[i]).map_err(|e| {
+        Error::new_message(format!(
+            "{}(): argument {} {}",
+            name,
+            i + 1,
+            e.result_error_message()
+        ))
+    })
+}
+
+macro_rules! impl_typed_scalar_function {
+    ($arity:expr; $($arg:ident $idx:tt),*) => {
+        impl<F, R, $($arg,)*> TypedScalarFunction<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> Result<R>,
+            // borrowed arguments only live for the call, so the function has to
+            // accept them with any lifetime
+            F: for<'a> Fn($($arg::Bound<'a>),*) -> Result<R>,
+            R: ToSqlResult,
+            $($arg: TypedArgument,)*
+        {
+            const ARITY: c_int = $arity;
+
+            #[allow(unused_variables)]
+            fn call(
+                &self,
+                name: &str,
+                context: *mut sqlite3_context,
+                values: &[*mut sqlite3_value],
+            ) -> Result<()> {
+                fn apply<F, R, $($arg,)*>(f: &F, args: ($($arg,)*)) -> Result<R>
+                where
+                    F: Fn($($arg),*) -> Result<R>,
+                {
+                    f($(args.$idx),*)
+                }
+                let args = ($(typed_argument::<$arg::Bound<'_>>(name, values, $idx)?,)*);
+                apply(self, args)?.to_sql_result(context)
+            }
+        }
+    };
+}
+
+impl_typed_scalar_function!(0;);
+impl_typed_scalar_function!(1; A 0);
+impl_typed_scalar_function!(2; A 0, B 1);
+impl_typed_scalar_function!(3; A 0, B 1, C 2);
+impl_typed_scalar_function!(4; A 0, B 1, C 2, D 3);
+impl_typed_scalar_function!(5; A 0, B 1, C 2, D 3, E 4);
+impl_typed_scalar_function!(6; A 0, B 1, C 2, D 3, E 4, G 5);
+impl_typed_scalar_function!(7; A 0, B 1, C 2, D 3, E 4, G 5, H 6);
+impl_typed_scalar_function!(8; A 0, B 1, C 2, D 3, E 4, G 5, H 6, I 7);
+
+/// Defines a new scalar function from a Rust function with typed arguments and
+/// return value. The number of arguments is inferred from the function signature,
+/// and every argument is checked against its [`FromSqlValue`] type before the
+/// function is called. A mismatched argument is reported as a SQL error, like
+/// `xyz_add(): argument 2 expected integer, got text`. Arguments like `&str` and
+/// `&[u8]` borrow the SQL value, so they can't outlive the call.
+///
+/// # Example
+/// ```rust
+/// define_typed_scalar_function(
+///     db,
+///     "xyz_add",
+///     |a: i64, b: i64| -> Result<i64> { Ok(a + b) },
+///     FunctionFlags::UTF8 | FunctionFlags::DETERMINISTIC,
+/// )?;
+/// ```
+pub fn define_typed_scalar_function<F, Args>(
+    db: *mut sqlite3,
+    name: &str,
+    x_func: F,
+    func_flags: FunctionFlags,
+) -> Result<()>
+where
+    F: TypedScalarFunction<Args>,
+{
+    let function_name = name.to_owned();
+    define_scalar_function(
+        db,
+        name,
+        F::ARITY,
+        move |context, values| x_func.call(&function_name, context, values),
+        func_flags,
     )
 }
 
@@ -141,6 +311,9 @@ where
 /// application "pointer" as any rust type. Can be accessed in the callback
 /// function as the 3rd argument, as a reference.
 /// <https://www.sqlite.org/c3ref/create_function.html#:~:text=The%20fifth%20parameter%20is%20an%20arbitrary%20pointer.>
+///
+/// Both `x_func` and `aux` are dropped once SQLite destroys the function, which
+/// happens when it's redefined or deleted, or when the connection is closed.
 pub fn define_scalar_function_with_aux<F, T>(
     db: *mut sqlite3,
     name: &str,
@@ -152,9 +325,7 @@ pub fn define_scalar_function_with_aux<F, T>(
 where
     F: Fn(*mut sqlite3_context, &[*mut sqlite3_value], &T) -> Result<()>,
 {
-    let function_pointer: *mut F = Box::into_raw(Box::new(x_func));
-    let aux_pointer: *mut T = Box::into_raw(Box::new(aux));
-    let app_pointer = Box::into_raw(Box::new((function_pointer, aux_pointer)));
+    let app_pointer: *mut (F, T) = Box::into_raw(Box::new((x_func, aux)));
 
     unsafe extern "C" fn x_func_wrapper<F, T>(
         context: *mut sqlite3_context,
@@ -163,21 +334,12 @@ where
     ) where
         F: Fn(*mut sqlite3_context, &[*mut sqlite3_value], &T) -> Result<()>,
     {
-        let x = sqlite3ext_user_data(context).cast::<(*mut F, *mut T)>();
-        let boxed_function = (*x).0;
-        let aux = (*x).1;
+        let (function, aux) = &*sqlite3ext_user_data(context).cast::<(F, T)>();
         // .collect slows things waaaay down, so stick with slice for now
         let args = slice::from_raw_parts(argv, argc as usize);
-        let b = Box::from_raw(aux);
-        match (*boxed_function)(context, args, &*b) {
-            Ok(()) => (),
-            Err(e) => {
-                if api::result_error(context, &e.result_error_message()).is_err() {
-                    api::result_error_code(context, SQLITE_INTERNAL);
-                }
-            }
+        if let Err(err) = catch_panic(|| function(context, args, aux)).and_then(|result| result) {
+            result_error(context, err);
         }
-        Box::into_raw(b);
     }
     create_function_v2(
         db,
@@ -188,7 +350,7 @@ where
         Some(x_func_wrapper::<F, T>),
         None,
         None,
-        None,
+        Some(destroy_boxed::<(F, T)>),
     )
 }
 
@@ -211,15 +373,40 @@ pub fn delete_scalar_function(
     )
 }
 
-// TODO only used for find_function, probably can combine with that return type?
+/// Fails to compile for an `F` that isn't zero-sized, once it's used.
+struct ZeroSized<F>(PhantomData<F>);
+
+impl<F> ZeroSized<F> {
+    const CHECK: () = assert!(
+        mem::size_of::<F>() == 0,
+        "raw scalar functions must be fn items or closures that don't capture anything"
+    );
+}
+
+/// Returns `ZeroSized::<F>` as the function it is, without any stored state.
+///
+/// # Safety
+/// `F` must be zero-sized, which `ZeroSized::<F>::CHECK` ensures.
+unsafe fn zero_sized_function<'a, F>() -> &'a F {
+    // a dangling pointer is a valid reference to a zero-sized type
+    &*ptr::NonNull::<F>::dangling().as_ptr()
+}
+
+/// Wraps `x_func` into a bare C function pointer, like the ones returned from
+/// [`VTabFind::find_function`](crate::table::VTabFind::find_function).
+///
+/// The returned pointer has no user data to store `x_func` in, so `x_func` has
+/// to be a plain `fn` item or a closure that doesn't capture anything. Anything
+/// else is a compile error.
 pub fn scalar_function_raw<F>(
     x_func: F,
 ) -> unsafe extern "C" fn(*mut sqlite3_context, i32, *mut *mut sqlite3_value)
 where
     F: Fn(*mut sqlite3_context, &[*mut sqlite3_value]) -> Result<()>,
 {
-    // TODO: how does x_func even get called here???
-    let _function_pointer: *mut F = Box::into_raw(Box::new(x_func));
+    let () = ZeroSized::<F>::CHECK;
+    // nothing to store, x_func_wrapper recreates the function itself
+    mem::forget(x_func);
 
     unsafe extern "C" fn x_func_wrapper<F>(
         context: *mut sqlite3_context,
@@ -228,20 +415,24 @@ where
     ) where
         F: Fn(*mut sqlite3_context, &[*mut sqlite3_value]) -> Result<()>,
     {
-        let boxed_function: *mut F = sqlite3ext_user_data(context).cast::<F>();
+        let x_func = zero_sized_function::<F>();
         let args = slice::from_raw_parts(argv, argc as usize);
-        match (*boxed_function)(context, args) {
-            Ok(()) => (),
-            Err(e) => {
-                if api::result_error(context, &e.result_error_message()).is_err() {
-                    api::result_error_code(context, SQLITE_INTERNAL);
-                }
-            }
+        if let Err(err) = catch_panic(|| x_func(context, args)).and_then(|result| result) {
+            result_error(context, err);
         }
     }
 
     x_func_wrapper::<F>
 }
+
+/// Same as [`scalar_function_raw`], with `aux` passed into every call. The
+/// returned pointer has to be returned from
+/// [`VTabFind::find_function`](crate::table::VTabFind::find_function) along
+/// with the function, so SQLite passes it back as the user data.
+///
+/// `x_func` has the same restriction as in [`scalar_function_raw`], which is a
+/// compile error for capturing closures. `aux` is leaked, since SQLite has no
+/// way to free the pointer that xFindFunction returns.
 pub fn scalar_function_raw_with_aux<F, T>(
     x_func: F,
     aux: T,
@@ -252,10 +443,9 @@ pub fn scalar_function_raw_with_aux<F, T>(
 where
     F: Fn(*mut sqlite3_context, &[*mut sqlite3_value], &T) -> Result<()>,
 {
-    // TODO: how does x_func even get called here???
-    let function_pointer: *mut F = Box::into_raw(Box::new(x_func));
+    let () = ZeroSized::<F>::CHECK;
+    mem::forget(x_func);
     let aux_pointer: *mut T = Box::into_raw(Box::new(aux));
-    let app_pointer = Box::into_raw(Box::new((function_pointer, aux_pointer)));
 
     unsafe extern "C" fn x_func_wrapper<F, T>(
         context: *mut sqlite3_context,
@@ -264,22 +454,13 @@ where
     ) where
         F: Fn(*mut sqlite3_context, &[*mut sqlite3_value], &T) -> Result<()>,
     {
-        let x = sqlite3ext_user_data(context).cast::<(*mut F, *mut T)>();
-        let boxed_function = (*x).0;
-        let aux = (*x).1;
-
+        let x_func = zero_sized_function::<F>();
+        let aux = &*sqlite3ext_user_data(context).cast::<T>();
         let args = slice::from_raw_parts(argv, argc as usize);
-        let b = Box::from_raw(aux);
-        match (*boxed_function)(context, args, &*b) {
-            Ok(()) => (),
-            Err(e) => {
-                if api::result_error(context, &e.result_error_message()).is_err() {
-                    api::result_error_code(context, SQLITE_INTERNAL);
-                }
-            }
+        if let Err(err) = catch_panic(|| x_func(context, args, aux)).and_then(|result| result) {
+            result_error(context, err);
         }
-        Box::into_raw(b);
     }
 
-    (x_func_wrapper::<F, T>, app_pointer.cast())
+    (x_func_wrapper::<F, T>, aux_pointer.cast())
 }
@@ -5,22 +5,31 @@
 use crate::constants::*;
 use std::ffi::CString;
 use std::marker::PhantomData;
-use std::marker::Sync;
 use std::os::raw::{c_char, c_int, c_void};
 use std::ptr;
 use std::slice;
 use std::str::Utf8Error;
 
-use crate::api::{mprintf, value_type, MprintfError, ValueType};
-use crate::errors::{Error, ErrorKind, Result};
+use crate::api::{
+    db_error, exec, libversion_number, mprintf, query, value_is_null, value_nochange,
+    vtab_nochange, MprintfError, Value,
+};
+use crate::errors::{catch_panic, Error, ErrorKind, Result};
 use crate::ext::{
     sqlite3, sqlite3_context, sqlite3_index_info, sqlite3_index_info_sqlite3_index_constraint,
     sqlite3_index_info_sqlite3_index_constraint_usage, sqlite3_index_info_sqlite3_index_orderby,
     sqlite3_module, sqlite3_value, sqlite3_vtab, sqlite3_vtab_cursor, sqlite3ext_create_module_v2,
-    sqlite3ext_declare_vtab, sqlite3ext_vtab_distinct, sqlite3ext_vtab_in,
-    sqlite3ext_vtab_in_first, sqlite3ext_vtab_in_next,
+    sqlite3ext_declare_vtab, sqlite3ext_free, sqlite3ext_vtab_collation, sqlite3ext_vtab_config,
+    sqlite3ext_vtab_distinct, sqlite3ext_vtab_in, sqlite3ext_vtab_in_first,
+    sqlite3ext_vtab_in_next, sqlite3ext_vtab_on_conflict, sqlite3ext_vtab_rhs_value,
+};
+use crate::{FromSqlValue, SqlValue, ToSqlResult};
+use bitflags::bitflags;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sqlite3ext_sys::{
+    SQLITE_FAIL, SQLITE_IGNORE, SQLITE_INDEX_SCAN_UNIQUE, SQLITE_REPLACE, SQLITE_ROLLBACK,
+    SQLITE_VTAB_CONSTRAINT_SUPPORT, SQLITE_VTAB_DIRECTONLY, SQLITE_VTAB_INNOCUOUS,
 };
-use serde::{Deserialize, Serialize};
 
 /// Possible operators for a given constraint, found and used in xBestIndex and xFilter.
 /// <https://www.sqlite.org/c3ref/c_index_constraint_eq.html>
@@ -118,7 +127,7 @@ impl IndexInfo {
     pub fn idx_flag(&self) -> i32 {
         unsafe { (*self.index_info).idxFlags }
     }
-    pub fn constraints(&self) -> Vec<Constraint> {
+    pub fn constraints(&self) -> Vec<Constraint<'_>> {
         let constraints = unsafe {
             slice::from_raw_parts(
                 (*self.index_info).aConstraint,
@@ -142,6 +151,7 @@ impl IndexInfo {
                 usage: z.1,
                 index_info: self.index_info,
                 constraint_idx: idx as i32,
+                _info: PhantomData,
             })
             .collect();
     }
@@ -164,7 +174,10 @@ impl IndexInfo {
             Ok(idxstr) => idxstr,
             Err(err) => {
                 return match err {
-                    MprintfError::Oom => Err(Error::new_message("OOM todo change to code?")),
+                    MprintfError::Oom => Err(Error::new_code(
+                        SQLITE_NOMEM,
+                        "out of memory copying the idxStr",
+                    )),
                     MprintfError::Nul(err) => Err(err.into()),
                 }
             }
@@ -175,6 +188,13 @@ impl IndexInfo {
         }
         Ok(())
     }
+    /// Stores `plan` as JSON in idxStr, for [`Plan::decode`] to read back in
+    /// [`VTabCursor::filter`].
+    pub fn set_plan<P: Serialize>(&mut self, plan: &Plan<P>) -> crate::Result<()> {
+        let idxstr = serde_json::to_string(plan)
+            .map_err(|e| Error::new_message(format!("could not serialize plan: {}", e)))?;
+        self.set_idxstr(&idxstr)
+    }
     pub fn set_estimated_rows(&mut self, value: i64) {
         unsafe {
             (*self.index_info).estimatedRows = value;
@@ -189,31 +209,109 @@ impl IndexInfo {
             (*self.index_info).estimatedCost = value;
         }
     }
-    // TODO ORDER BY
 
-    // TODO the u64 by itself isn't very useful - offer a func that does the
-    // manually bitshifting/checks internally.
-    pub fn columns_used(&self) -> u64 {
-        unsafe { (*self.index_info).colUsed }
+    /// Tells SQLite that the rows will be returned in the order that
+    /// [`IndexInfo::order_bys`] asks for, so it doesn't need to sort them again.
+    /// <https://www.sqlite.org/vtab.html#order_by_and_orderbyconsumed>
+    pub fn set_order_by_consumed(&mut self, value: bool) {
+        unsafe {
+            (*self.index_info).orderByConsumed = c_int::from(value);
+        }
+    }
+
+    /// Sets the `SQLITE_INDEX_SCAN_*` flags of the plan, ex
+    /// [`IndexScanFlags::UNIQUE`] when it returns at most one row.
+    pub fn set_idx_flags(&mut self, flags: IndexScanFlags) {
+        unsafe {
+            (*self.index_info).idxFlags = flags.bits();
+        }
+    }
+
+    /// The columns of the table that the statement actually uses.
+    pub fn columns_used(&self) -> ColumnsUsed {
+        ColumnsUsed(unsafe { (*self.index_info).colUsed })
+    }
+
+    /// How the statement will use the rows, from
+    /// [`sqlite3_vtab_distinct`](https://www.sqlite.org/c3ref/vtab_distinct.html).
+    /// Always [`Distinct::Ordered`], which makes no promises, before SQLite 3.38.0.
+    pub fn distinct(&self) -> Distinct {
+        if libversion_number() < 3038000 {
+            return Distinct::Ordered;
+        }
+        match unsafe { sqlite3ext_vtab_distinct(self.index_info) } {
+            1 => Distinct::Grouped,
+            2 => Distinct::Distinct,
+            3 => Distinct::DistinctOrdered,
+            _ => Distinct::Ordered,
+        }
+    }
+}
+
+bitflags! {
+    /// Flags for [`IndexInfo::set_idx_flags`].
+    /// <https://www.sqlite.org/c3ref/c_index_scan_unique.html>
+    pub struct IndexScanFlags: c_int {
+        /// "the scan visits at most one row"
+        const UNIQUE = SQLITE_INDEX_SCAN_UNIQUE as c_int;
+    }
+}
+
+/// The `colUsed` mask of xBestIndex, from [`IndexInfo::columns_used`].
+/// <https://www.sqlite.org/vtab.html#colused>
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColumnsUsed(u64);
+
+impl ColumnsUsed {
+    /// Whether the column at index `column` is used. The mask only has room for
+    /// 63 columns, so this is true for every column past the 63rd if any of
+    /// them are used.
+    pub fn contains(&self, column: i32) -> bool {
+        match column {
+            0..=62 => self.0 & (1 << column) != 0,
+            _ if column < 0 => false,
+            _ => self.0 & (1 << 63) != 0,
+        }
     }
-    pub fn distinct(&self) -> i32 {
-        unsafe { sqlite3ext_vtab_distinct(self.index_info) }
+
+    /// The raw `colUsed` bitmask.
+    pub fn bits(&self) -> u64 {
+        self.0
     }
-    // TODO idxFlags
+}
+
+/// The possible return values of
+/// [`sqlite3_vtab_distinct`](https://www.sqlite.org/c3ref/vtab_distinct.html),
+/// which let a table skip work when the statement doesn't need every row, or
+/// doesn't need them in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Distinct {
+    /// 0: every row must be returned, in the order of [`IndexInfo::order_bys`]
+    /// when the order is consumed.
+    Ordered,
+    /// 1: rows with the same values in the ORDER BY columns must be adjacent,
+    /// but their order doesn't matter, ex for a GROUP BY.
+    Grouped,
+    /// 2: only one row with the same values in the ORDER BY columns is needed,
+    /// in any order, ex for a DISTINCT.
+    Distinct,
+    /// 3: same as [`Distinct::Distinct`], but the rows must also be ordered.
+    DistinctOrdered,
 }
 
 /// Wraps the raw sqlite3_index_constraint and sqlite3_index_constraint_usage
 /// C structs for ergonomic use in Rust.
 #[derive(Debug)]
-pub struct Constraint {
+pub struct Constraint<'a> {
     pub constraint: sqlite3_index_info_sqlite3_index_constraint,
     pub usage: *mut sqlite3_index_info_sqlite3_index_constraint_usage,
     // needed for sqlite3_vtab_* methods
     index_info: *mut sqlite3_index_info,
     constraint_idx: i32,
+    _info: PhantomData<&'a IndexInfo>,
 }
 
-impl Constraint {
+impl<'a> Constraint<'a> {
     pub fn column_idx(&self) -> i32 {
         (self.constraint).iColumn
     }
@@ -232,47 +330,151 @@ impl Constraint {
         unsafe { (*self.usage).omit = u8::from(value) }
     }
 
-    // # TODO check for sqlite version 3.38.0 (2022-02-22)
+    /// The argv index given with [`Constraint::set_argv_index`], or 0 if the
+    /// constraint isn't passed to `filter`.
+    pub fn argv_index(&self) -> i32 {
+        unsafe { (*self.usage).argvIndex }
+    }
+
+    /// Whether [`Constraint::set_omit`] was set.
+    pub fn omit(&self) -> bool {
+        unsafe { (*self.usage).omit != 0 }
+    }
+
+    /// The right-hand side value of the constraint, ex `10` in `value > 10`,
+    /// with [`sqlite3_vtab_rhs_value`](https://www.sqlite.org/c3ref/vtab_rhs_value.html).
+    /// Only available when it's known at planning time, like literals, and never
+    /// for bound parameters or column references. The value borrows the
+    /// [`IndexInfo`], since it's only valid during this xBestIndex call.
+    /// Always None before SQLite 3.38.0.
+    pub fn rhs_value(&self) -> Option<Value<'a>> {
+        if libversion_number() < 3038000 {
+            return None;
+        }
+        let mut value: *mut sqlite3_value = ptr::null_mut();
+        let rc =
+            unsafe { sqlite3ext_vtab_rhs_value(self.index_info, self.constraint_idx, &mut value) };
+        if rc != SQLITE_OKAY || value.is_null() {
+            return None;
+        }
+        Some(Value::from_ptr(value))
+    }
+
+    /// The name of the collating sequence the constraint should be evaluated
+    /// with, ex `"BINARY"` or `"NOCASE"`, with
+    /// [`sqlite3_vtab_collation`](https://www.sqlite.org/c3ref/vtab_collation.html).
+    /// Always None before SQLite 3.22.0.
+    pub fn collation(&self) -> Option<&str> {
+        if libversion_number() < 3022000 {
+            return None;
+        }
+        let collation = unsafe { sqlite3ext_vtab_collation(self.index_info, self.constraint_idx) };
+        if collation.is_null() {
+            return None;
+        }
+        unsafe { CStr::from_ptr(collation) }.to_str().ok()
+    }
+
+    /// Whether SQLite can pass every value of this `IN (...)` constraint to
+    /// a single `filter` call, with
+    /// [`sqlite3_vtab_in`](https://www.sqlite.org/c3ref/vtab_in.html).
+    /// Always false before SQLite 3.38.0.
     pub fn can_process_all_in(&self) -> bool {
-        unsafe { sqlite3ext_vtab_in(self.index_info, self.constraint_idx, -1) == 1 }
+        self.vtab_in(-1)
     }
+
+    /// Asks SQLite to pass every value of this `IN (...)` constraint to a
+    /// single `filter` call, as a list to read with [`InValues`]. Returns false
+    /// when it can't, in which case `filter` is called once for every value
+    /// instead, ex before SQLite 3.38.0.
     pub fn enable_process_all_in(&self) -> bool {
-        unsafe { sqlite3ext_vtab_in(self.index_info, self.constraint_idx, 1) == 1 }
+        self.vtab_in(1)
     }
+
+    /// Undoes [`Constraint::enable_process_all_in`].
     pub fn disable_process_all_in(&self) -> bool {
-        unsafe { sqlite3ext_vtab_in(self.index_info, self.constraint_idx, 0) == 1 }
+        self.vtab_in(0)
+    }
+
+    fn vtab_in(&self, handle: i32) -> bool {
+        if libversion_number() < 3038000 {
+            return false;
+        }
+        unsafe { sqlite3ext_vtab_in(self.index_info, self.constraint_idx, handle) == 1 }
     }
 }
 
-pub struct InValues {
-    list_value: *mut sqlite3_value,
-    yielded_first: bool,
-    value: *mut sqlite3_value,
+/// An iterator over the values of an `IN (...)` constraint in `filter`,
+/// converted to `T`. See [`Constraint::enable_process_all_in`].
+/// <https://www.sqlite.org/c3ref/vtab_in_first.html>
+pub struct InValues<T> {
+    state: InValuesState,
+    phantom: PhantomData<T>,
 }
-impl InValues {
+
+enum InValuesState {
+    First(*mut sqlite3_value),
+    Next(*mut sqlite3_value),
+    Single(*mut sqlite3_value),
+    Done,
+}
+
+impl<T: for<'a> FromSqlValue<'a>> InValues<T> {
+    /// The values of the list passed to `filter` for a constraint that
+    /// [`Constraint::enable_process_all_in`] returned true for.
     pub fn new(list_value: *mut sqlite3_value) -> Self {
         InValues {
-            list_value,
-            yielded_first: false,
-            value: unsafe { std::mem::zeroed() },
+            state: InValuesState::First(list_value),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Same as [`InValues::new`] when `all_in` is true, otherwise `value` is a
+    /// single value of the `IN (...)`, because `filter` is called once per value.
+    /// Pass in what [`Constraint::enable_process_all_in`] returned in xBestIndex,
+    /// ex through a [`Plan`], to handle both cases the same way.
+    pub fn from_argument(value: *mut sqlite3_value, all_in: bool) -> Self {
+        if all_in {
+            Self::new(value)
+        } else {
+            InValues {
+                state: InValuesState::Single(value),
+                phantom: PhantomData,
+            }
         }
     }
 }
-impl Iterator for InValues {
-    type Item = Result<*mut sqlite3_value>;
+
+impl<T: for<'a> FromSqlValue<'a>> Iterator for InValues<T> {
+    type Item = Result<T>;
     fn next(&mut self) -> Option<Self::Item> {
-        let rc = if self.yielded_first {
-            unsafe { sqlite3ext_vtab_in_next(self.list_value, &mut self.value) }
-        } else {
-            self.yielded_first = true;
-            unsafe { sqlite3ext_vtab_in_first(self.list_value, &mut self.value) }
+        let mut value: *mut sqlite3_value = ptr::null_mut();
+        let (list, rc) = match std::mem::replace(&mut self.state, InValuesState::Done) {
+            InValuesState::Done => return None,
+            InValuesState::Single(value) => return Some(T::from_sql_value(&value)),
+            InValuesState::First(_) if libversion_number() < 3038000 => {
+                return Some(Err(Error::new_code(
+                    SQLITE_MISUSE,
+                    "IN constraint lists require SQLite 3.38.0 or newer",
+                )))
+            }
+            InValuesState::First(list) => {
+                (list, unsafe { sqlite3ext_vtab_in_first(list, &mut value) })
+            }
+            InValuesState::Next(list) => {
+                (list, unsafe { sqlite3ext_vtab_in_next(list, &mut value) })
+            }
         };
-        if rc == SQLITE_DONE {
-            None
-        } else if rc == SQLITE_ERROR {
-            Some(Err(Error::new_message("TODO")))
-        } else {
-            Some(Ok(self.value))
+        match rc {
+            SQLITE_OKAY => {
+                self.state = InValuesState::Next(list);
+                Some(T::from_sql_value(&value))
+            }
+            SQLITE_DONE => None,
+            rc => Some(Err(Error::new_code(
+                rc,
+                "could not read the values of an IN constraint",
+            ))),
         }
     }
 }
@@ -299,20 +501,426 @@ impl OrderBy {
     }
 }
 
+/// A typed query plan, built in [`VTab::best_index`] and decoded in
+/// [`VTabCursor::filter`], instead of hand-encoding it into idxNum/idxStr.
+/// `P` is any serializable type describing the plan. Constraints passed to
+/// `filter` are given names with [`Plan::argument`], which assigns their argv
+/// indexes so `filter` can look them up by name.
+///
+/// ```rust,ignore
+/// #[derive(Serialize, Deserialize)]
+/// enum Scan { Full, Range }
+///
+/// fn best_index(&mut self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+///     let mut plan = Plan::new(Scan::Full);
+///     for mut constraint in info.constraints() {
+///         if constraint.usable() && constraint.op() == Some(ConstraintOperator::GE) {
+///             plan.argument(&mut constraint, "start");
+///             plan.plan = Scan::Range;
+///         }
+///     }
+///     info.set_plan(&plan).map_err(|_| BestIndexError::Error)?;
+///     Ok(())
+/// }
+///
+/// fn filter(&mut self, _idx_num: c_int, idx_str: Option<&str>, values: &[*mut sqlite3_value]) -> Result<()> {
+///     let (scan, arguments) = Plan::<Scan>::decode(idx_str, values)?;
+///     let start: Option<i64> = arguments.get("start").map(|v| v.get()).transpose()?;
+///     // ...
+/// }
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Plan<P> {
+    pub plan: P,
+    arguments: Vec<String>,
+}
+
+impl<P> Plan<P> {
+    pub fn new(plan: P) -> Self {
+        Plan {
+            plan,
+            arguments: vec![],
+        }
+    }
+
+    /// Passes the constraint's right-hand value to `filter` as `name`, by
+    /// giving it the next argv index. Call [`Constraint::set_omit`] separately
+    /// when SQLite doesn't need to double-check the constraint.
+    pub fn argument(&mut self, constraint: &mut Constraint, name: &str) {
+        self.arguments.push(name.to_owned());
+        constraint.set_argv_index(self.arguments.len() as i32);
+    }
+
+    /// Passes the query's `LIMIT` and `OFFSET` to `filter`, read back with
+    /// [`PlanArguments::limit_offset`]. Call this last in `best_index`: they can
+    /// only be pushed down when every other constraint is passed to `filter`
+    /// and omitted, and the ORDER BY (if any) is consumed, since otherwise
+    /// SQLite would still drop or reorder rows after the table limits them.
+    /// Returns false, and requests nothing, when that isn't the case.
+    ///
+    /// SQLite only offers these constraints for queries on a single virtual
+    /// table, without aggregates, DISTINCT or GROUP BY, since SQLite 3.38.0.
+    /// The OFFSET is omitted, so the table has to skip those rows itself.
+    /// <https://www.sqlite.org/vtab.html#limit_and_offset>
+    pub fn limit_offset(&mut self, info: &IndexInfo) -> bool {
+        let mut constraints = info.constraints();
+        let (mut limit_offset, others): (Vec<_>, Vec<_>) = constraints.iter_mut().partition(|c| {
+            matches!(
+                c.op(),
+                Some(ConstraintOperator::LIMIT | ConstraintOperator::OFFSET)
+            )
+        });
+        let consumed = others
+            .iter()
+            .all(|c| c.usable() && c.argv_index() > 0 && c.omit());
+        let order_consumed =
+            unsafe { (*info.index_info).nOrderBy == 0 || (*info.index_info).orderByConsumed != 0 };
+        if !consumed || !order_consumed || limit_offset.iter().any(|c| !c.usable()) {
+            return false;
+        }
+        let mut limited = false;
+        for constraint in limit_offset.iter_mut() {
+            if constraint.op() == Some(ConstraintOperator::LIMIT) {
+                self.argument(constraint, PLAN_LIMIT);
+                limited = true;
+            } else {
+                self.argument(constraint, PLAN_OFFSET);
+            }
+            constraint.set_omit(true);
+        }
+        limited
+    }
+}
+
+/// Argument names of [`Plan::limit_offset`], which can't clash with column names.
+const PLAN_LIMIT: &str = "$limit";
+const PLAN_OFFSET: &str = "$offset";
+
+/// The `LIMIT` and `OFFSET` of a query, from [`PlanArguments::limit_offset`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LimitOffset {
+    /// The most rows the query returns, `None` when unlimited.
+    pub limit: Option<i64>,
+    /// How many rows to skip before returning any, `None` when none.
+    pub offset: Option<i64>,
+}
+
+impl<P: DeserializeOwned> Plan<P> {
+    /// Reads the plan stored by [`IndexInfo::set_plan`] from `filter`'s
+    /// `idx_str`, along with its named arguments from `values`.
+    pub fn decode<'a>(
+        idx_str: Option<&str>,
+        values: &'a [*mut sqlite3_value],
+    ) -> Result<(P, PlanArguments<'a>)> {
+        let idx_str = idx_str.ok_or_else(|| Error::new_message("missing query plan"))?;
+        let plan: Plan<P> = serde_json::from_str(idx_str)
+            .map_err(|e| Error::new_message(format!("invalid query plan: {}", e)))?;
+        if plan.arguments.len() != values.len() {
+            return Err(Error::new_message(format!(
+                "query plan expected {} arguments, got {}",
+                plan.arguments.len(),
+                values.len()
+            )));
+        }
+        let arguments = PlanArguments {
+            names: plan.arguments,
+            values,
+        };
+        Ok((plan.plan, arguments))
+    }
+}
+
+/// The arguments given to `filter`, by the names of [`Plan::argument`].
+#[derive(Debug)]
+pub struct PlanArguments<'a> {
+    names: Vec<String>,
+    values: &'a [*mut sqlite3_value],
+}
+
+impl<'a> PlanArguments<'a> {
+    /// The argument named `name`, if the plan has one.
+    pub fn get(&self, name: &str) -> Option<Value<'a>> {
+        let idx = self.names.iter().position(|n| n == name)?;
+        Value::at(self.values, idx)
+    }
+
+    /// The LIMIT and OFFSET requested with [`Plan::limit_offset`]. Negative
+    /// values mean no limit or offset, like in SQLite.
+    pub fn limit_offset(&self) -> Result<LimitOffset> {
+        let get = |name| -> Result<Option<i64>> {
+            match self.get(name) {
+                Some(value) => Ok(Some(value.get::<i64>()?).filter(|n| *n >= 0)),
+                None => Ok(None),
+            }
+        };
+        Ok(LimitOffset {
+            limit: get(PLAN_LIMIT)?,
+            offset: get(PLAN_OFFSET)?,
+        })
+    }
+}
+
 /// Possible errors to return in xBestIndex.
 pub enum BestIndexError {
     /// Returns SQLITE_CONSTRAINT. See <https://www.sqlite.org/vtab.html#return_value>
     Constraint,
     Error,
 }
-#[repr(transparent)]
-struct Module<'vtab, T: VTab<'vtab>> {
+/// A `sqlite3_module`, followed by the methods that newer SQLite versions added
+/// after the version that sqlite3ext-sys was generated from. SQLite only reads
+/// methods up to the module's `iVersion`, so older versions never see them.
+/// The libsqlite3-sys bindings used by the `static` feature already have them.
+#[repr(C)]
+struct Module {
     base: sqlite3_module,
+    /// <https://www.sqlite.org/vtab.html#the_xintegrity_method>, iVersion 4
+    #[cfg(not(feature = "static"))]
+    x_integrity: Option<
+        unsafe extern "C" fn(
+            *mut sqlite3_vtab,
+            *const c_char,
+            *const c_char,
+            c_int,
+            *mut *mut c_char,
+        ) -> c_int,
+    >,
+}
+
+// SQLite 3.44.0 declares xIntegrity as the next field after xShadowName, the
+// last one in the sqlite3_module from sqlite3ext-sys.
+#[cfg(not(feature = "static"))]
+const _: () = assert!(
+    std::mem::size_of::<Module>()
+        == std::mem::size_of::<sqlite3_module>() + std::mem::size_of::<usize>()
+);
+
+/// Everything SQLite needs to keep alive for a registered module: the
+/// [`Module`] method table itself, and the optional auxillary object.
+/// Boxed and passed as the module's client data, and dropped by SQLite through
+/// [`destroy_module_data`] when the module is replaced or the connection closes.
+struct ModuleData<Aux> {
+    module: Module,
+    aux: Option<Aux>,
+}
+
+unsafe extern "C" fn destroy_module_data<Aux>(p: *mut c_void) {
+    if !p.is_null() {
+        drop(Box::from_raw(p.cast::<ModuleData<Aux>>()));
+    }
+}
+
+/// The auxillary object stored in a module's client data, if any.
+unsafe fn module_aux<'a, Aux>(p_app: *mut c_void) -> Option<&'a Aux> {
+    p_app
+        .cast::<ModuleData<Aux>>()
+        .as_ref()
+        .and_then(|data| data.aux.as_ref())
+}
+
+/// Builds and registers a virtual table module, with the `sqlite3_module`
+/// methods filled in from the traits that `T` implements. Capabilities can be
+/// combined freely, ex a writeable table function that overloads functions:
+///
+/// ```rust,ignore
+/// ModuleBuilder::<MyTable>::new("my_table")
+///     .eponymous_only()
+///     .writeable()
+///     .find_function()
+///     .register(db)?;
+/// ```
+///
+/// By default the module is a regular virtual table, that is only usable after a
+/// `CREATE VIRTUAL TABLE xxx USING my_table(...)`. See
+/// [`ModuleBuilder::eponymous`] and [`ModuleBuilder::eponymous_only`] for the
+/// alternatives.
+pub struct ModuleBuilder<'vtab, T: VTab<'vtab>> {
+    name: String,
+    module: Module,
+    aux: Option<T::Aux>,
     phantom: PhantomData<&'vtab T>,
 }
 
-unsafe impl<'vtab, T: VTab<'vtab>> Send for Module<'vtab, T> {}
-unsafe impl<'vtab, T: VTab<'vtab>> Sync for Module<'vtab, T> {}
+impl<'vtab, T: VTab<'vtab> + 'vtab> ModuleBuilder<'vtab, T> {
+    /// A read-only module named `name`, with the xCreate/xConnect, xBestIndex,
+    /// xOpen and cursor methods from [`VTab`] and [`VTabCursor`].
+    pub fn new(name: &str) -> Self {
+        ModuleBuilder {
+            name: name.to_owned(),
+            module: Module {
+                base: sqlite3_module {
+                    iVersion: 2,
+                    xCreate: Some(rust_create::<T>),
+                    xConnect: Some(rust_connect::<T>),
+                    xBestIndex: Some(rust_best_index::<T>),
+                    xDisconnect: Some(rust_disconnect::<T>),
+                    xDestroy: Some(rust_destroy::<T>),
+                    xOpen: Some(rust_open::<T>),
+                    xClose: Some(rust_close::<T::Cursor>),
+                    xFilter: Some(rust_filter::<T::Cursor>),
+                    xNext: Some(rust_next::<T::Cursor>),
+                    xEof: Some(rust_eof::<T::Cursor>),
+                    xColumn: Some(rust_column::<T::Cursor>),
+                    xRowid: Some(rust_rowid::<T::Cursor>),
+                    xUpdate: None,
+                    xBegin: None,
+                    xSync: None,
+                    xCommit: None,
+                    xRollback: None,
+                    xFindFunction: None,
+                    xRename: None,
+                    xSavepoint: None,
+                    xRelease: None,
+                    xRollbackTo: None,
+                    xShadowName: None,
+                    #[cfg(feature = "static")]
+                    xIntegrity: None,
+                },
+                #[cfg(not(feature = "static"))]
+                x_integrity: None,
+            },
+            aux: None,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Passes `aux` to every [`VTab::create`] and [`VTab::connect`] call. It's
+    /// dropped when the module is replaced or the connection is closed.
+    pub fn aux(mut self, aux: T::Aux) -> Self {
+        self.aux = Some(aux);
+        self
+    }
+
+    /// Makes the module an "eponymous-only" virtual table, aka a table function,
+    /// that can only be used as `SELECT * FROM my_table(...)` and never in a
+    /// `CREATE VIRTUAL TABLE` statement.
+    /// <https://www.sqlite.org/vtab.html#eponymous_only_virtual_tables>
+    pub fn eponymous_only(mut self) -> Self {
+        self.module.base.xCreate = None;
+        self
+    }
+
+    /// Makes the module an "eponymous" virtual table, that can be used directly
+    /// as `SELECT * FROM my_table`, and also with `CREATE VIRTUAL TABLE xxx USING
+    /// my_table(...)`. SQLite requires xCreate and xConnect to be the same
+    /// method for this, so [`VTab::connect`] is called in both cases and
+    /// [`VTab::create`] never is. [`VTab::destroy`] is only called on a
+    /// `DROP TABLE` of a created table, never for the eponymous one.
+    /// <https://www.sqlite.org/vtab.html#eponymous_virtual_tables>
+    pub fn eponymous(mut self) -> Self {
+        self.module.base.xCreate = Some(rust_connect::<T>);
+        self
+    }
+
+    /// Supports `INSERT`/`UPDATE`/`DELETE` through [`VTabWriteable::update`].
+    pub fn writeable(mut self) -> Self
+    where
+        T: VTabWriteable<'vtab>,
+    {
+        self.module.base.xUpdate = Some(rust_update::<T>);
+        self
+    }
+
+    /// Calls the [`VTabWriteableWithTransactions`] methods when a transaction
+    /// begins, syncs, commits or rolls back.
+    pub fn transactions(mut self) -> Self
+    where
+        T: VTabWriteableWithTransactions<'vtab>,
+    {
+        self.module.base.xBegin = Some(rust_begin::<T>);
+        self.module.base.xSync = Some(rust_sync::<T>);
+        self.module.base.xCommit = Some(rust_commit::<T>);
+        self.module.base.xRollback = Some(rust_rollback::<T>);
+        self
+    }
+
+    /// Same as [`ModuleBuilder::transactions`], and also calls the
+    /// [`VTabWriteableNestedTransactions`] methods on `SAVEPOINT`, `RELEASE`
+    /// and `ROLLBACK TO`.
+    pub fn nested_transactions(mut self) -> Self
+    where
+        T: VTabWriteableNestedTransactions<'vtab>,
+    {
+        self = self.transactions();
+        self.module.base.xSavepoint = Some(rust_savepoint::<T>);
+        self.module.base.xRelease = Some(rust_release::<T>);
+        self.module.base.xRollbackTo = Some(rust_rollback_to::<T>);
+        self
+    }
+
+    /// Supports `ALTER TABLE ... RENAME TO` through [`VTabRename::rename`].
+    pub fn renameable(mut self) -> Self
+    where
+        T: VTabRename<'vtab>,
+    {
+        self.module.base.xRename = Some(rust_rename::<T>);
+        self
+    }
+
+    /// Marks the tables named in [`VTabShadowNames::shadow_names`] as shadow
+    /// tables, which bumps the module to version 3.
+    pub fn shadow_names(mut self) -> Self
+    where
+        T: VTabShadowNames<'vtab>,
+    {
+        self.module.base.iVersion = self.module.base.iVersion.max(3);
+        self.module.base.xShadowName = Some(rust_shadow_name::<T>);
+        self
+    }
+
+    /// Reports problems found by [`VTabIntegrity::integrity`] in
+    /// `PRAGMA integrity_check` and `PRAGMA quick_check`. This needs a module
+    /// version 4, so it's only enabled when the SQLite that loaded the extension
+    /// is 3.44.0 or newer, and is a no-op on older versions.
+    pub fn integrity(mut self) -> Self
+    where
+        T: VTabIntegrity<'vtab>,
+    {
+        if libversion_number() >= 3044000 {
+            self.module.base.iVersion = self.module.base.iVersion.max(4);
+            #[cfg(not(feature = "static"))]
+            {
+                self.module.x_integrity = Some(rust_integrity::<T>);
+            }
+            #[cfg(feature = "static")]
+            {
+                self.module.base.xIntegrity = Some(rust_integrity::<T>);
+            }
+        }
+        self
+    }
+
+    /// Lets the table overload SQL functions with [`VTabFind::find_function`].
+    pub fn find_function(mut self) -> Self
+    where
+        T: VTabFind<'vtab>,
+    {
+        self.module.base.xFindFunction = Some(rust_find_function::<T>);
+        self
+    }
+
+    /// Registers the module on the given database connection with
+    /// [`sqlite3_create_module_v2`](https://www.sqlite.org/c3ref/create_module.html).
+    pub fn register(self, db: *mut sqlite3) -> Result<()> {
+        let cname = CString::new(self.name)?;
+        let data = Box::into_raw(Box::new(ModuleData {
+            module: self.module,
+            aux: self.aux,
+        }));
+        let result = unsafe {
+            sqlite3ext_create_module_v2(
+                db,
+                cname.as_ptr(),
+                ptr::addr_of!((*data).module.base),
+                data.cast::<c_void>(),
+                Some(destroy_module_data::<T::Aux>),
+            )
+        };
+        if result != SQLITE_OKAY {
+            return Err(Error::new(ErrorKind::TableFunction(result)));
+        }
+        Ok(())
+    }
+}
 
 /// Define a table function on the given sqlite3 database.
 /// "Table function" is the same as "eponymous-only" virtual table
@@ -322,290 +930,73 @@ pub fn define_table_function<'vtab, T: VTab<'vtab> + 'vtab>(
     name: &str,
     aux: Option<T::Aux>,
 ) -> Result<()> {
-    let m = &Module {
-        base: sqlite3_module {
-            iVersion: 2,
-            xCreate: None,
-            xConnect: Some(rust_connect::<T>),
-            xBestIndex: Some(rust_best_index::<T>),
-            xDisconnect: Some(rust_disconnect::<T>),
-            xDestroy: Some(rust_destroy::<T>),
-            xOpen: Some(rust_open::<T>),
-            xClose: Some(rust_close::<T::Cursor>),
-            xFilter: Some(rust_filter::<T::Cursor>),
-            xNext: Some(rust_next::<T::Cursor>),
-            xEof: Some(rust_eof::<T::Cursor>),
-            xColumn: Some(rust_column::<T::Cursor>),
-            xRowid: Some(rust_rowid::<T::Cursor>),
-            xUpdate: None,
-            xBegin: None,
-            xSync: None,
-            xCommit: None,
-            xRollback: None,
-            xFindFunction: None,
-            xRename: None,
-            xSavepoint: None,
-            xRelease: None,
-            xRollbackTo: None,
-            xShadowName: None,
-        },
-        phantom: PhantomData::<&'vtab T>,
-    };
-    let cname = CString::new(name)?;
-    let p_app = match aux {
-        Some(aux) => {
-            let boxed_aux: *mut T::Aux = Box::into_raw(Box::new(aux));
-            boxed_aux.cast::<c_void>()
-        }
-        None => ptr::null_mut(),
-    };
-    let result = unsafe {
-        sqlite3ext_create_module_v2(
-            db,
-            cname.as_ptr(),
-            &m.base,
-            p_app,
-            Some(destroy_aux::<T::Aux>),
-        )
-    };
-    if result != SQLITE_OKAY {
-        return Err(Error::new(ErrorKind::TableFunction(result)));
+    ModuleBuilder::<T> {
+        aux,
+        ..ModuleBuilder::new(name)
     }
-    Ok(())
+    .eponymous_only()
+    .register(db)
 }
+
+/// Same as [`define_table_function`], with [`ModuleBuilder::find_function`].
 pub fn define_table_function_with_find<'vtab, T: VTabFind<'vtab> + 'vtab>(
     db: *mut sqlite3,
     name: &str,
     aux: Option<T::Aux>,
 ) -> Result<()> {
-    let m = &Module {
-        base: sqlite3_module {
-            iVersion: 2,
-            xCreate: None,
-            xConnect: Some(rust_connect::<T>),
-            xBestIndex: Some(rust_best_index::<T>),
-            xDisconnect: Some(rust_disconnect::<T>),
-            xDestroy: Some(rust_destroy::<T>),
-            xOpen: Some(rust_open::<T>),
-            xClose: Some(rust_close::<T::Cursor>),
-            xFilter: Some(rust_filter::<T::Cursor>),
-            xNext: Some(rust_next::<T::Cursor>),
-            xEof: Some(rust_eof::<T::Cursor>),
-            xColumn: Some(rust_column::<T::Cursor>),
-            xRowid: Some(rust_rowid::<T::Cursor>),
-            xUpdate: None,
-            xBegin: None,
-            xSync: None,
-            xCommit: None,
-            xRollback: None,
-            xFindFunction: Some(rust_find_function::<T>),
-            xRename: None,
-            xSavepoint: None,
-            xRelease: None,
-            xRollbackTo: None,
-            xShadowName: None,
-        },
-        phantom: PhantomData::<&'vtab T>,
-    };
-    let cname = CString::new(name)?;
-    let p_app = match aux {
-        Some(aux) => {
-            let boxed_aux: *mut T::Aux = Box::into_raw(Box::new(aux));
-            boxed_aux.cast::<c_void>()
-        }
-        None => ptr::null_mut(),
-    };
-    let result = unsafe {
-        sqlite3ext_create_module_v2(
-            db,
-            cname.as_ptr(),
-            &m.base,
-            p_app,
-            Some(destroy_aux::<T::Aux>),
-        )
-    };
-    if result != SQLITE_OKAY {
-        return Err(Error::new(ErrorKind::TableFunction(result)));
-    }
-    Ok(())
-}
-
-// source: https://github.com/rusqlite/rusqlite/blob/12a6d3c1b1bdd58ca7103619b8a133e76d30decd/src/vtab/mod.rs#L931
-unsafe extern "C" fn destroy_aux<T>(p: *mut c_void) {
-    if !p.is_null() {
-        drop(Box::from_raw(p.cast::<T>()));
+    ModuleBuilder::<T> {
+        aux,
+        ..ModuleBuilder::new(name)
     }
+    .eponymous_only()
+    .find_function()
+    .register(db)
 }
 
 /// Define a virtual table on the sqlite3 database connection. Optionally
-/// pass in an auxillary object, which
+/// pass in an auxillary object, which is given to every `create`/`connect` call.
 pub fn define_virtual_table<'vtab, T: VTab<'vtab> + 'vtab>(
     db: *mut sqlite3,
     name: &str,
     aux: Option<T::Aux>,
 ) -> Result<()> {
-    let m = &Module {
-        base: sqlite3_module {
-            iVersion: 2,
-            xCreate: Some(rust_create::<T>),
-            xConnect: Some(rust_connect::<T>),
-            xBestIndex: Some(rust_best_index::<T>),
-            xDisconnect: Some(rust_disconnect::<T>),
-            xDestroy: Some(rust_destroy::<T>),
-            xOpen: Some(rust_open::<T>),
-            xClose: Some(rust_close::<T::Cursor>),
-            xFilter: Some(rust_filter::<T::Cursor>),
-            xNext: Some(rust_next::<T::Cursor>),
-            xEof: Some(rust_eof::<T::Cursor>),
-            xColumn: Some(rust_column::<T::Cursor>),
-            xRowid: Some(rust_rowid::<T::Cursor>),
-            xUpdate: None,
-            xBegin: None,
-            xSync: None,
-            xCommit: None,
-            xRollback: None,
-            xFindFunction: None,
-            xRename: None,
-            xSavepoint: None,
-            xRelease: None,
-            xRollbackTo: None,
-            xShadowName: None,
-        },
-        phantom: PhantomData::<&'vtab T>,
-    };
-    let cname = CString::new(name)?;
-    let app_pointer = match aux {
-        Some(aux) => Box::into_raw(Box::new(aux)).cast::<c_void>(),
-        None => ptr::null_mut(),
-    };
-    let result = unsafe {
-        sqlite3ext_create_module_v2(
-            db,
-            cname.as_ptr(),
-            &m.base,
-            app_pointer,
-            Some(destroy_aux::<T::Aux>),
-        )
-    };
-    if result != SQLITE_OKAY {
-        return Err(Error::new(ErrorKind::TableFunction(result)));
+    ModuleBuilder::<T> {
+        aux,
+        ..ModuleBuilder::new(name)
     }
-    Ok(())
+    .register(db)
 }
 
+/// Same as [`define_virtual_table`], with [`ModuleBuilder::find_function`].
 pub fn define_virtual_table_with_find<'vtab, T: VTabFind<'vtab> + 'vtab>(
     db: *mut sqlite3,
     name: &str,
     aux: Option<T::Aux>,
 ) -> Result<()> {
-    let m = &Module {
-        base: sqlite3_module {
-            iVersion: 2,
-            xCreate: Some(rust_create::<T>),
-            xConnect: Some(rust_connect::<T>),
-            xBestIndex: Some(rust_best_index::<T>),
-            xDisconnect: Some(rust_disconnect::<T>),
-            xDestroy: Some(rust_destroy::<T>),
-            xOpen: Some(rust_open::<T>),
-            xClose: Some(rust_close::<T::Cursor>),
-            xFilter: Some(rust_filter::<T::Cursor>),
-            xNext: Some(rust_next::<T::Cursor>),
-            xEof: Some(rust_eof::<T::Cursor>),
-            xColumn: Some(rust_column::<T::Cursor>),
-            xRowid: Some(rust_rowid::<T::Cursor>),
-            xUpdate: None,
-            xBegin: None,    //Some(rust_begin::<T>),
-            xSync: None,     //Some(rust_sync::<T>),
-            xCommit: None,   //Some(rust_commit::<T>),
-            xRollback: None, //Some(rust_rollback::<T>),
-            xFindFunction: Some(rust_find_function::<T>),
-            xRename: None,
-            xSavepoint: None,
-            xRelease: None,
-            xRollbackTo: None,
-            xShadowName: None,
-        },
-        phantom: PhantomData::<&'vtab T>,
-    };
-    let cname = CString::new(name)?;
-    let p_app = match aux {
-        Some(aux) => {
-            let boxed_aux: *mut T::Aux = Box::into_raw(Box::new(aux));
-            boxed_aux.cast::<c_void>()
-        }
-        None => ptr::null_mut(),
-    };
-    let result = unsafe {
-        sqlite3ext_create_module_v2(
-            db,
-            cname.as_ptr(),
-            &m.base,
-            p_app,
-            Some(destroy_aux::<T::Aux>),
-        )
-    };
-    if result != SQLITE_OKAY {
-        return Err(Error::new(ErrorKind::TableFunction(result)));
+    ModuleBuilder::<T> {
+        aux,
+        ..ModuleBuilder::new(name)
     }
-    Ok(())
+    .find_function()
+    .register(db)
 }
+
+/// Same as [`define_virtual_table`], with [`ModuleBuilder::writeable`].
 pub fn define_virtual_table_writeable<'vtab, T: VTabWriteable<'vtab> + 'vtab>(
     db: *mut sqlite3,
     name: &str,
     aux: Option<T::Aux>,
 ) -> Result<()> {
-    let m = &Module {
-        base: sqlite3_module {
-            iVersion: 2,
-            xCreate: Some(rust_create::<T>),
-            xConnect: Some(rust_connect::<T>),
-            xBestIndex: Some(rust_best_index::<T>),
-            xDisconnect: Some(rust_disconnect::<T>),
-            xDestroy: Some(rust_destroy::<T>),
-            xOpen: Some(rust_open::<T>),
-            xClose: Some(rust_close::<T::Cursor>),
-            xFilter: Some(rust_filter::<T::Cursor>),
-            xNext: Some(rust_next::<T::Cursor>),
-            xEof: Some(rust_eof::<T::Cursor>),
-            xColumn: Some(rust_column::<T::Cursor>),
-            xRowid: Some(rust_rowid::<T::Cursor>),
-            xUpdate: Some(rust_update::<T>),
-            xBegin: None,    //Some(rust_begin::<T>),
-            xSync: None,     //Some(rust_sync::<T>),
-            xCommit: None,   //Some(rust_commit::<T>),
-            xRollback: None, //Some(rust_rollback::<T>),
-            xFindFunction: None,
-            xRename: None,
-            xSavepoint: None,
-            xRelease: None,
-            xRollbackTo: None,
-            xShadowName: None,
-        },
-        phantom: PhantomData::<&'vtab T>,
-    };
-    let cname = CString::new(name)?;
-    let p_app = match aux {
-        Some(aux) => {
-            let boxed_aux: *mut T::Aux = Box::into_raw(Box::new(aux));
-            boxed_aux.cast::<c_void>()
-        }
-        None => ptr::null_mut(),
-    };
-    let result = unsafe {
-        sqlite3ext_create_module_v2(
-            db,
-            cname.as_ptr(),
-            &m.base,
-            p_app,
-            Some(destroy_aux::<T::Aux>),
-        )
-    };
-    if result != SQLITE_OKAY {
-        return Err(Error::new(ErrorKind::TableFunction(result)));
+    ModuleBuilder::<T> {
+        aux,
+        ..ModuleBuilder::new(name)
     }
-    Ok(())
+    .writeable()
+    .register(db)
 }
 
+/// Same as [`define_virtual_table`], with [`ModuleBuilder::writeable`] and
+/// [`ModuleBuilder::transactions`].
 pub fn define_virtual_table_writeable_with_transactions<
     'vtab,
     T: VTabWriteableWithTransactions<'vtab> + 'vtab,
@@ -614,115 +1005,122 @@ pub fn define_virtual_table_writeable_with_transactions<
     name: &str,
     aux: Option<T::Aux>,
 ) -> Result<()> {
-    let m = &Module {
-        base: sqlite3_module {
-            iVersion: 2,
-            xCreate: Some(rust_create::<T>),
-            xConnect: Some(rust_connect::<T>),
-            xBestIndex: Some(rust_best_index::<T>),
-            xDisconnect: Some(rust_disconnect::<T>),
-            xDestroy: Some(rust_destroy::<T>),
-            xOpen: Some(rust_open::<T>),
-            xClose: Some(rust_close::<T::Cursor>),
-            xFilter: Some(rust_filter::<T::Cursor>),
-            xNext: Some(rust_next::<T::Cursor>),
-            xEof: Some(rust_eof::<T::Cursor>),
-            xColumn: Some(rust_column::<T::Cursor>),
-            xRowid: Some(rust_rowid::<T::Cursor>),
-            xUpdate: Some(rust_update::<T>),
-            xBegin: Some(rust_begin::<T>),
-            xSync: Some(rust_sync::<T>),
-            xCommit: Some(rust_commit::<T>),
-            xRollback: Some(rust_rollback::<T>),
-            xFindFunction: None,
-            xRename: None,
-            xSavepoint: None,
-            xRelease: None,
-            xRollbackTo: None,
-            xShadowName: None,
-        },
-        phantom: PhantomData::<&'vtab T>,
-    };
-    let cname = CString::new(name)?;
-    let p_app = match aux {
-        Some(aux) => {
-            let boxed_aux: *mut T::Aux = Box::into_raw(Box::new(aux));
-            boxed_aux.cast::<c_void>()
-        }
-        None => ptr::null_mut(),
-    };
-    let result = unsafe {
-        sqlite3ext_create_module_v2(
-            db,
-            cname.as_ptr(),
-            &m.base,
-            p_app,
-            Some(destroy_aux::<T::Aux>),
-        )
-    };
-    if result != SQLITE_OKAY {
-        return Err(Error::new(ErrorKind::TableFunction(result)));
+    ModuleBuilder::<T> {
+        aux,
+        ..ModuleBuilder::new(name)
     }
-    Ok(())
+    .writeable()
+    .transactions()
+    .register(db)
 }
 
+/// Same as [`define_table_function`], with [`ModuleBuilder::writeable`].
 pub fn define_virtual_table_writeablex<'vtab, T: VTabWriteable<'vtab> + 'vtab>(
     db: *mut sqlite3,
     name: &str,
     aux: Option<T::Aux>,
 ) -> Result<()> {
-    let m = &Module {
-        base: sqlite3_module {
-            iVersion: 2,
-            xCreate: None,
-            xConnect: Some(rust_connect::<T>),
-            xBestIndex: Some(rust_best_index::<T>),
-            xDisconnect: Some(rust_disconnect::<T>),
-            xDestroy: Some(rust_destroy::<T>),
-            xOpen: Some(rust_open::<T>),
-            xClose: Some(rust_close::<T::Cursor>),
-            xFilter: Some(rust_filter::<T::Cursor>),
-            xNext: Some(rust_next::<T::Cursor>),
-            xEof: Some(rust_eof::<T::Cursor>),
-            xColumn: Some(rust_column::<T::Cursor>),
-            xRowid: Some(rust_rowid::<T::Cursor>),
-            xUpdate: Some(rust_update::<T>),
-            xBegin: None,    //Some(rust_begin::<T>),
-            xSync: None,     //Some(rust_sync::<T>),
-            xCommit: None,   //Some(rust_commit::<T>),
-            xRollback: None, //Some(rust_rollback::<T>),
-            xFindFunction: None,
-            xRename: None,
-            xSavepoint: None,
-            xRelease: None,
-            xRollbackTo: None,
-            xShadowName: None,
-        },
-        phantom: PhantomData::<&'vtab T>,
-    };
-    let cname = CString::new(name)?;
-    let p_app = match aux {
-        Some(aux) => {
-            let boxed_aux: *mut T::Aux = Box::into_raw(Box::new(aux));
-            boxed_aux.cast::<c_void>()
+    ModuleBuilder::<T> {
+        aux,
+        ..ModuleBuilder::new(name)
+    }
+    .eponymous_only()
+    .writeable()
+    .register(db)
+}
+
+/// Options for a virtual table, set with [`vtab_config`].
+/// <https://www.sqlite.org/c3ref/c_vtab_constraint_support.html>
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VTabConfig {
+    /// SQLITE_VTAB_CONSTRAINT_SUPPORT: when true, the table's
+    /// [`VTabWriteable::update`] handles `ON CONFLICT` clauses itself, with
+    /// [`on_conflict`]. When false (the default), SQLite assumes the update
+    /// either fully succeeds or fails with no changes.
+    ConstraintSupport(bool),
+    /// SQLITE_VTAB_INNOCUOUS: the table is safe to use from triggers, views and
+    /// other schema objects, even with `PRAGMA trusted_schema=OFF`. Only set this
+    /// for tables with no side effects, that don't leak private information.
+    Innocuous,
+    /// SQLITE_VTAB_DIRECTONLY: the table can only be used in top-level SQL, and
+    /// never from triggers or views. For tables with side effects.
+    DirectOnly,
+    /// SQLITE_VTAB_USES_ALL_SCHEMAS: the table reads from every attached
+    /// database, so queries on it need a read transaction on all of them.
+    /// Requires SQLite 3.42.0 or later, [`vtab_config`] returns an error on
+    /// older versions.
+    UsesAllSchemas,
+}
+
+/// Not in the vendored sqlite3.h, added in SQLite 3.42.0
+const SQLITE_VTAB_USES_ALL_SCHEMAS: c_int = 4;
+
+/// Configures the virtual table that's currently being created or connected, with
+/// [`sqlite3_vtab_config`](https://www.sqlite.org/c3ref/vtab_config.html).
+/// Can only be called inside of [`VTab::create`] or [`VTab::connect`].
+pub fn vtab_config(db: *mut sqlite3, config: VTabConfig) -> Result<()> {
+    let (op, arg) = match config {
+        VTabConfig::ConstraintSupport(enabled) => {
+            (SQLITE_VTAB_CONSTRAINT_SUPPORT as c_int, enabled as c_int)
         }
-        None => ptr::null_mut(),
-    };
-    let result = unsafe {
-        sqlite3ext_create_module_v2(
-            db,
-            cname.as_ptr(),
-            &m.base,
-            p_app,
-            Some(destroy_aux::<T::Aux>),
-        )
+        VTabConfig::Innocuous => (SQLITE_VTAB_INNOCUOUS as c_int, 0),
+        VTabConfig::DirectOnly => (SQLITE_VTAB_DIRECTONLY as c_int, 0),
+        VTabConfig::UsesAllSchemas => (SQLITE_VTAB_USES_ALL_SCHEMAS, 0),
     };
-    if result != SQLITE_OKAY {
-        return Err(Error::new(ErrorKind::TableFunction(result)));
+    if config == VTabConfig::UsesAllSchemas && libversion_number() < 3042000 {
+        return Err(Error::new_code(
+            SQLITE_MISUSE,
+            "sqlite3_vtab_config(UsesAllSchemas) requires SQLite 3.42.0 or later",
+        ));
+    }
+    let rc = unsafe { sqlite3ext_vtab_config(db, op, arg) };
+    if rc != SQLITE_OKAY {
+        return Err(Error::new_code(
+            rc,
+            format!(
+                "sqlite3_vtab_config({:?}) failed, it can only be called inside of VTab::create or VTab::connect",
+                config
+            ),
+        ));
     }
     Ok(())
 }
 
+/// The conflict resolution algorithm of the current `INSERT`/`UPDATE`, ie the
+/// `OR REPLACE` in `INSERT OR REPLACE INTO ...`.
+/// <https://www.sqlite.org/lang_conflict.html>
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictMode {
+    Rollback,
+    Abort,
+    Fail,
+    Ignore,
+    Replace,
+}
+
+/// The [`ConflictMode`] of the statement that's currently calling
+/// [`VTabWriteable::update`], with
+/// [`sqlite3_vtab_on_conflict`](https://www.sqlite.org/c3ref/vtab_on_conflict.html).
+/// Only meaningful for tables with [`VTabConfig::ConstraintSupport`] enabled.
+///
+/// For `Rollback`, `Abort` and `Fail`, `update` should return a
+/// [`SQLITE_CONSTRAINT`] error on a conflict, and SQLite handles the rest. For
+/// `Ignore`, it should skip the conflicting row and return Ok. For `Replace`, it
+/// should overwrite the conflicting row.
+pub fn on_conflict(db: *mut sqlite3) -> ConflictMode {
+    match unsafe { sqlite3ext_vtab_on_conflict(db) } as u32 {
+        SQLITE_ROLLBACK => ConflictMode::Rollback,
+        SQLITE_FAIL => ConflictMode::Fail,
+        SQLITE_IGNORE => ConflictMode::Ignore,
+        SQLITE_REPLACE => ConflictMode::Replace,
+        // SQLITE_ABORT is the default conflict mode
+        _ => ConflictMode::Abort,
+    }
+}
+
+/// A virtual table. SQLite owns the table from xCreate/xConnect until
+/// xDisconnect/xDestroy, so a table can't borrow anything, and every method
+/// only gets the table for the duration of the call.
 pub trait VTab<'vtab>: Sized {
     type Aux;
     type Cursor: VTabCursor;
@@ -741,17 +1139,27 @@ pub trait VTab<'vtab>: Sized {
         args: VTabArguments,
     ) -> Result<(String, Self)>;
 
-    fn best_index(&self, info: IndexInfo) -> core::result::Result<(), BestIndexError>;
+    fn best_index(&mut self, info: IndexInfo) -> core::result::Result<(), BestIndexError>;
 
-    fn open(&'vtab mut self) -> Result<Self::Cursor>;
+    /// Opens a new cursor on the table. SQLite can have several cursors open
+    /// at once, ex in a self-join, and can write to the table while they're
+    /// open, so cursors can't borrow from the table. Keep any state that cursors
+    /// need to read in an `Arc` on the table instead, and clone it into each
+    /// cursor. SQLite closes every cursor before the table is disconnected.
+    fn open(&mut self) -> Result<Self::Cursor>;
 
-    fn destroy(&self) -> Result<()> {
+    /// Called on a `DROP TABLE`, to delete any persistent state like shadow
+    /// tables. The table is dropped afterwards, unless this fails, in which
+    /// case the table stays until it's disconnected.
+    fn destroy(&mut self) -> Result<()> {
         Ok(())
     }
 }
 
 pub trait VTabWriteable<'vtab>: VTab<'vtab> {
-    fn update(&'vtab mut self, operation: UpdateOperation, p_rowid: *mut i64) -> Result<()>;
+    /// Called for every `INSERT`/`UPDATE`/`DELETE` on the table. See [`on_conflict`]
+    /// to support `INSERT OR REPLACE` and friends.
+    fn update(&mut self, operation: UpdateOperation<'_>, p_rowid: *mut i64) -> Result<()>;
 }
 
 pub type FindResult = (
@@ -762,22 +1170,163 @@ pub type FindResult = (
 
 pub trait VTabFind<'vtab>: VTab<'vtab> {
     // TODO should be able to return SQLITE_INDEX_CONSTRAINT_FUNCTION or more
-    fn find_function(&'vtab mut self, argc: i32, name: &str) -> Option<FindResult>;
+    fn find_function(&mut self, argc: i32, name: &str) -> Option<FindResult>;
 }
 
 pub trait VTabWriteableWithTransactions<'vtab>: VTabWriteable<'vtab> {
-    fn begin(&'vtab mut self) -> Result<()>;
-    fn sync(&'vtab mut self) -> Result<()>;
-    fn commit(&'vtab mut self) -> Result<()>;
-    fn rollback(&'vtab mut self) -> Result<()>;
+    fn begin(&mut self) -> Result<()>;
+    fn sync(&mut self) -> Result<()>;
+    fn commit(&mut self) -> Result<()>;
+    fn rollback(&mut self) -> Result<()>;
+}
+
+/// Nested transactions with `SAVEPOINT`, `RELEASE` and `ROLLBACK TO`.
+/// SQLite only tracks savepoints for tables that are in a transaction, so this
+/// requires [`VTabWriteableWithTransactions`] as well.
+/// <https://www.sqlite.org/vtab.html#the_xsavepoint_xrelease_and_xrollbackto_methods>
+pub trait VTabWriteableNestedTransactions<'vtab>: VTabWriteableWithTransactions<'vtab> {
+    /// Saves the current state of the table as savepoint `id`. Savepoints with
+    /// an id greater than or equal to `id` are implicitly released.
+    fn savepoint(&mut self, id: c_int) -> Result<()>;
+    /// Releases all savepoints with an id greater than or equal to `id`,
+    /// keeping their changes.
+    fn release(&mut self, id: c_int) -> Result<()>;
+    /// Reverts the table to the state of savepoint `id`, which stays active.
+    /// Later savepoints are implicitly released.
+    fn rollback_to(&mut self, id: c_int) -> Result<()>;
+}
+
+/// Support for `ALTER TABLE ... RENAME TO` on a virtual table.
+/// <https://www.sqlite.org/vtab.html#the_xrename_method>
+pub trait VTabRename<'vtab>: VTab<'vtab> {
+    /// Called before the table is renamed to `new_name`, to rename any backing
+    /// storage like shadow tables, ex with [`crate::api::exec`]. It runs inside
+    /// the same transaction as the `ALTER TABLE`, and returning an error aborts
+    /// the rename.
+    fn rename(&mut self, new_name: &str) -> Result<()>;
+}
+
+/// Declares the "shadow tables" of a virtual table, regular tables named
+/// `<vtab>_<suffix>` that store its data, like FTS5's `xxx_data` or R*Tree's
+/// `xxx_node`. When `SQLITE_DBCONFIG_DEFENSIVE` is on, SQLite makes shadow
+/// tables read-only to ordinary SQL, so they can only be changed by the virtual
+/// table itself. [`ShadowTables`] helps to create, drop and rename them.
+/// <https://www.sqlite.org/vtab.html#the_xshadowname_method>
+pub trait VTabShadowNames<'vtab>: VTab<'vtab> {
+    /// The suffixes of every shadow table, ex `&["data", "config"]`. Matched
+    /// case-insensitively.
+    fn shadow_names() -> &'static [&'static str];
 }
 
-pub trait VTabWriteableNestedTransactions<'vtab>: VTabWriteable<'vtab> {
-    fn savepoint(&'vtab mut self, id: c_int) -> Result<()>;
-    fn release(&'vtab mut self, id: c_int) -> Result<()>;
-    fn rollback_to(&'vtab mut self, id: c_int) -> Result<()>;
+/// Helpers for the shadow tables of one virtual table, meant to be used from
+/// [`VTab::create`], [`VTab::destroy`] and [`VTabRename::rename`]. All table
+/// names are quoted and qualified with the virtual table's schema.
+///
+/// ```rust,ignore
+/// fn create(db: *mut sqlite3, aux: Option<&()>, args: VTabArguments) -> Result<(String, MyTable)> {
+///     let shadow = ShadowTables::new(db, &args);
+///     shadow.create_table("data", "id integer primary key, value")?;
+///     // ...
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ShadowTables {
+    db: *mut sqlite3,
+    schema: String,
+    table: String,
 }
 
+impl ShadowTables {
+    /// Shadow tables of the virtual table being created or connected with `args`.
+    pub fn new(db: *mut sqlite3, args: &VTabArguments) -> Self {
+        ShadowTables {
+            db,
+            schema: args.database_name.clone(),
+            table: args.table_name.clone(),
+        }
+    }
+
+    /// The unquoted name of a shadow table, ex `"xxx_data"` for `"data"`.
+    pub fn table_name(&self, suffix: &str) -> String {
+        format!("{}_{}", self.table, suffix)
+    }
+
+    /// The quoted, schema-qualified name of a shadow table, ex
+    /// `"main"."xxx_data"`, to use in SQL.
+    pub fn qualified_name(&self, suffix: &str) -> String {
+        format!(
+            "{}.{}",
+            quote_identifier(&self.schema),
+            quote_identifier(&self.table_name(suffix))
+        )
+    }
+
+    /// Creates the shadow table with the given column definitions, ex
+    /// `create_table("data", "id integer primary key, value blob")`.
+    pub fn create_table(&self, suffix: &str, columns: &str) -> Result<()> {
+        self.exec(&format!(
+            "CREATE TABLE {}({})",
+            self.qualified_name(suffix),
+            columns
+        ))
+    }
+
+    /// Drops the shadow table, if it exists.
+    pub fn drop_table(&self, suffix: &str) -> Result<()> {
+        self.exec(&format!(
+            "DROP TABLE IF EXISTS {}",
+            self.qualified_name(suffix)
+        ))
+    }
+
+    /// Renames every shadow table in `suffixes` to follow the virtual table's
+    /// `new_table` name. Later calls use the new names.
+    pub fn rename_tables(&mut self, suffixes: &[&str], new_table: &str) -> Result<()> {
+        for suffix in suffixes {
+            self.exec(&format!(
+                "ALTER TABLE {} RENAME TO {}",
+                self.qualified_name(suffix),
+                quote_identifier(&format!("{}_{}", new_table, suffix))
+            ))?;
+        }
+        self.table = new_table.to_owned();
+        Ok(())
+    }
+
+    /// Runs SQL statements that don't return rows, with [`crate::api::exec`].
+    pub fn exec(&self, sql: &str) -> Result<()> {
+        exec(self.db, sql)
+    }
+
+    /// Runs a query and collects its rows, with [`crate::api::query`].
+    pub fn query<R, F>(&self, sql: &str, f: F) -> Result<Vec<R>>
+    where
+        F: FnMut(&[Value]) -> Result<R>,
+    {
+        query(self.db, sql, f)
+    }
+}
+
+fn quote_identifier(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+/// Checks a virtual table's backing storage for corruption, during
+/// `PRAGMA integrity_check`. Requires SQLite 3.44.0 or newer, see
+/// [`ModuleBuilder::integrity`].
+/// <https://www.sqlite.org/vtab.html#the_xintegrity_method>
+pub trait VTabIntegrity<'vtab>: VTab<'vtab> {
+    /// Checks the table `table` in the `schema` database. `flags` has
+    /// [`INTEGRITY_QUICK_CHECK`] set for `PRAGMA quick_check`, where only cheap
+    /// checks should be made. Return a description of any corruption that was
+    /// found as `Ok(Some(...))`, which is reported as a row in the pragma's
+    /// results. `Err` is only for failures to run the check, like OOMs.
+    fn integrity(&self, schema: &str, table: &str, flags: c_int) -> Result<Option<String>>;
+}
+
+/// Set in [`VTabIntegrity::integrity`]'s `flags` during a `PRAGMA quick_check`.
+pub const INTEGRITY_QUICK_CHECK: c_int = 1;
+
 pub trait VTabCursor: Sized {
     fn filter(
         &mut self,
@@ -791,6 +1340,62 @@ pub trait VTabCursor: Sized {
     fn rowid(&self) -> Result<i64>;
 }
 
+/// A [`VTabCursor`] that returns each column as a [`SqlValue`], instead of
+/// writing it into a raw `sqlite3_context`. Every `VTabTypedCursor` is a
+/// `VTabCursor`, so it can be used as the `Cursor` of any [`VTab`].
+pub trait VTabTypedCursor: Sized {
+    fn filter(
+        &mut self,
+        idx_num: c_int,
+        idx_str: Option<&str>,
+        values: &[*mut sqlite3_value],
+    ) -> Result<()>;
+    fn next(&mut self) -> Result<()>;
+    fn eof(&self) -> bool;
+    /// The value of column `i` of the current row. Text and blobs can be
+    /// borrowed from the cursor.
+    fn column(&self, i: c_int) -> Result<SqlValue<'_>>;
+    /// Called instead of [`VTabTypedCursor::column`] when SQLite only reads
+    /// column `i` for an UPDATE that doesn't change it ([`vtab_nochange`]).
+    /// Returning None skips the column, and xUpdate gets None for it in
+    /// [`UpdateOperation::Update`]. By default the column is read as usual.
+    fn column_nochange(&self, i: c_int) -> Result<Option<SqlValue<'_>>> {
+        VTabTypedCursor::column(self, i).map(Some)
+    }
+    fn rowid(&self) -> Result<i64>;
+}
+
+impl<C: VTabTypedCursor> VTabCursor for C {
+    fn filter(
+        &mut self,
+        idx_num: c_int,
+        idx_str: Option<&str>,
+        values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        VTabTypedCursor::filter(self, idx_num, idx_str, values)
+    }
+    fn next(&mut self) -> Result<()> {
+        VTabTypedCursor::next(self)
+    }
+    fn eof(&self) -> bool {
+        VTabTypedCursor::eof(self)
+    }
+    fn column(&self, ctx: *mut sqlite3_context, i: c_int) -> Result<()> {
+        let value = if vtab_nochange(ctx) {
+            match self.column_nochange(i)? {
+                Some(value) => value,
+                None => return Ok(()),
+            }
+        } else {
+            VTabTypedCursor::column(self, i)?
+        };
+        value.to_sql_result(ctx)
+    }
+    fn rowid(&self) -> Result<i64> {
+        VTabTypedCursor::rowid(self)
+    }
+}
+
 use std::ffi::CStr;
 
 /// Represents all the arguments given to the virtual table implementation
@@ -859,8 +1464,51 @@ fn process_create_args(
         arguments: arguments.to_vec(),
     })
 }
+/// Hands the error's message to SQLite through `err_msg` (freeing any previous
+/// message), and returns the error's (extended) result code. Every virtual table
+/// method reports its errors through here.
+unsafe fn report_error(err_msg: *mut *mut c_char, err: Error) -> c_int {
+    let code = err.code_extended() as c_int;
+    if err_msg.is_null() {
+        return code;
+    }
+    // NUL characters would truncate the message anyway, so only keep what comes before
+    let message = err.result_error_message();
+    let message = message.split('\0').next().unwrap_or_default();
+    if let Ok(msg) = mprintf(message) {
+        // "...the virtual table implementation must free any preexisting string
+        // using sqlite3_free() prior to assigning a new string to zErrMsg"
+        if !(*err_msg).is_null() {
+            sqlite3ext_free((*err_msg).cast::<c_void>());
+        }
+        *err_msg = msg;
+    }
+    code
+}
+
+/// [`report_error`] for methods on a virtual table.
+unsafe fn vtab_error(vtab: *mut sqlite3_vtab, err: Error) -> c_int {
+    report_error(ptr::addr_of_mut!((*vtab).zErrMsg), err)
+}
+
+/// [`report_error`] for methods on a virtual table cursor, which report errors
+/// on the cursor's virtual table.
+unsafe fn cursor_error(cursor: *mut sqlite3_vtab_cursor, err: Error) -> c_int {
+    vtab_error((*cursor).pVtab, err)
+}
+
+/// Declares the schema of the table that's being created or connected, with
+/// [`sqlite3_declare_vtab`](https://www.sqlite.org/c3ref/declare_vtab.html).
+unsafe fn declare_vtab(db: *mut sqlite3, sql: String) -> Result<()> {
+    let c_sql = CString::new(sql)?;
+    let rc = sqlite3ext_declare_vtab(db, c_sql.as_ptr());
+    if rc != SQLITE_OKAY {
+        return Err(db_error(db, rc));
+    }
+    Ok(())
+}
+
 /// <https://www.sqlite.org/vtab.html#the_xcreate_method>
-// TODO set error message properly
 unsafe extern "C" fn rust_create<'vtab, T>(
     db: *mut sqlite3,
     aux: *mut c_void,
@@ -872,38 +1520,25 @@ unsafe extern "C" fn rust_create<'vtab, T>(
 where
     T: VTab<'vtab>,
 {
-    let aux = aux.cast::<T::Aux>();
+    let aux = module_aux::<T::Aux>(aux);
     let args = match process_create_args(argc, argv) {
         Ok(args) => args,
-        Err(_) => return SQLITE_ERROR,
+        Err(err) => return report_error(err_msg, err.into()),
     };
-    match T::create(db, aux.as_ref(), args) {
-        Ok((sql, vtab)) => match CString::new(sql) {
-            Ok(c_sql) => {
-                let rc = sqlite3ext_declare_vtab(db, c_sql.as_ptr());
-                if rc == SQLITE_OKAY {
-                    let boxed_vtab: *mut T = Box::into_raw(Box::new(vtab));
-                    *pp_vtab = boxed_vtab.cast::<sqlite3_vtab>();
-                    SQLITE_OKAY
-                } else {
-                    rc
-                }
+    match catch_panic(|| T::create(db, aux, args)).and_then(|result| result) {
+        Ok((sql, vtab)) => match declare_vtab(db, sql) {
+            Ok(()) => {
+                let boxed_vtab: *mut T = Box::into_raw(Box::new(vtab));
+                *pp_vtab = boxed_vtab.cast::<sqlite3_vtab>();
+                SQLITE_OKAY
             }
-            Err(_err) => SQLITE_ERROR,
+            Err(err) => report_error(err_msg, err),
         },
-        Err(err) => {
-            if let ErrorKind::Message(msg) = err.kind() {
-                if let Ok(err) = mprintf(msg) {
-                    *err_msg = err;
-                }
-            };
-            err.code()
-        }
+        Err(err) => report_error(err_msg, err),
     }
 }
 
 /// <https://www.sqlite.org/vtab.html#the_xconnect_method>
-// TODO set error message properly
 unsafe extern "C" fn rust_connect<'vtab, T>(
     db: *mut sqlite3,
     aux: *mut c_void,
@@ -915,38 +1550,25 @@ unsafe extern "C" fn rust_connect<'vtab, T>(
 where
     T: VTab<'vtab>,
 {
-    let aux = aux.cast::<T::Aux>();
+    let aux = module_aux::<T::Aux>(aux);
     let args = match process_create_args(argc, argv) {
         Ok(args) => args,
-        Err(_) => return SQLITE_ERROR,
+        Err(err) => return report_error(err_msg, err.into()),
     };
-    match T::connect(db, aux.as_ref(), args) {
-        Ok((sql, vtab)) => match CString::new(sql) {
-            Ok(c_sql) => {
-                let rc = sqlite3ext_declare_vtab(db, c_sql.as_ptr());
-                if rc == SQLITE_OKAY {
-                    let boxed_vtab: *mut T = Box::into_raw(Box::new(vtab));
-                    *pp_vtab = boxed_vtab.cast::<sqlite3_vtab>();
-                    SQLITE_OKAY
-                } else {
-                    rc
-                }
+    match catch_panic(|| T::connect(db, aux, args)).and_then(|result| result) {
+        Ok((sql, vtab)) => match declare_vtab(db, sql) {
+            Ok(()) => {
+                let boxed_vtab: *mut T = Box::into_raw(Box::new(vtab));
+                *pp_vtab = boxed_vtab.cast::<sqlite3_vtab>();
+                SQLITE_OKAY
             }
-            Err(_err) => SQLITE_ERROR,
+            Err(err) => report_error(err_msg, err),
         },
-        Err(err) => {
-            if let ErrorKind::Message(msg) = err.kind() {
-                if let Ok(err) = mprintf(msg) {
-                    *err_msg = err;
-                }
-            };
-            err.code()
-        }
+        Err(err) => report_error(err_msg, err),
     }
 }
 
 /// <https://www.sqlite.org/vtab.html#the_xbestindex_method>
-// TODO set error message properly
 unsafe extern "C" fn rust_best_index<'vtab, T>(
     vtab: *mut sqlite3_vtab,
     index_info: *mut sqlite3_index_info,
@@ -955,17 +1577,21 @@ where
     T: VTab<'vtab>,
 {
     let vt = vtab.cast::<T>();
-    match (*vt).best_index(IndexInfo { index_info }) {
-        Ok(_) => SQLITE_OKAY,
-        Err(e) => match e {
-            BestIndexError::Constraint => SQLITE_CONSTRAINT,
-            BestIndexError::Error => SQLITE_ERROR,
-        },
+    match catch_panic(|| (*vt).best_index(IndexInfo { index_info })) {
+        Ok(Ok(_)) => SQLITE_OKAY,
+        // SQLite ignores the message for SQLITE_CONSTRAINT, and tries another plan
+        Ok(Err(BestIndexError::Constraint)) => vtab_error(
+            vtab,
+            Error::new_code(SQLITE_CONSTRAINT, "unusable combination of constraints"),
+        ),
+        Ok(Err(BestIndexError::Error)) => {
+            vtab_error(vtab, Error::new_code(SQLITE_ERROR, "best_index failed"))
+        }
+        Err(err) => vtab_error(vtab, err),
     }
 }
 
 /// <https://www.sqlite.org/vtab.html#the_xdisconnect_method>
-// TODO set error message properly
 unsafe extern "C" fn rust_disconnect<'vtab, T>(vtab: *mut sqlite3_vtab) -> c_int
 where
     T: VTab<'vtab>,
@@ -974,12 +1600,13 @@ where
         return SQLITE_OKAY;
     }
     let vtab = vtab.cast::<T>();
-    drop(Box::from_raw(vtab));
-    SQLITE_OKAY
+    match catch_panic(|| drop(Box::from_raw(vtab))) {
+        Ok(()) => SQLITE_OKAY,
+        Err(err) => err.code_extended() as c_int,
+    }
 }
 
 /// <https://www.sqlite.org/vtab.html#the_xdestroy_method>
-// TODO set error message properly
 unsafe extern "C" fn rust_destroy<'vtab, T>(vtab: *mut sqlite3_vtab) -> c_int
 where
     T: VTab<'vtab>,
@@ -988,14 +1615,15 @@ where
         return SQLITE_OKAY;
     }
     let vt = vtab.cast::<T>();
-    match (*vt).destroy() {
-        Ok(_) => SQLITE_OKAY,
-        Err(err) => err.code(),
+    match catch_panic(|| (*vt).destroy()).and_then(|result| result) {
+        // "If xDestroy succeeds, SQLite will not call xDisconnect", so this is
+        // the last chance to free the table
+        Ok(_) => rust_disconnect::<T>(vtab),
+        Err(err) => vtab_error(vtab, err),
     }
 }
 
 /// <https://www.sqlite.org/vtab.html#the_xopen_method>
-// TODO set error message properly
 unsafe extern "C" fn rust_open<'vtab, T: 'vtab>(
     vtab: *mut sqlite3_vtab,
     pp_cursor: *mut *mut sqlite3_vtab_cursor,
@@ -1004,86 +1632,88 @@ where
     T: VTab<'vtab>,
 {
     let vt = vtab.cast::<T>();
-    match (*vt).open() {
+    match catch_panic(|| (*vt).open()).and_then(|result| result) {
         Ok(cursor) => {
             let boxed_cursor: *mut T::Cursor = Box::into_raw(Box::new(cursor));
             *pp_cursor = boxed_cursor.cast::<sqlite3_vtab_cursor>();
             SQLITE_OKAY
         }
-        Err(err) => err.code(),
+        Err(err) => vtab_error(vtab, err),
     }
 }
 
-// https://www.sqlite.org/vtab.html#the_xupdate_method
+/// The write operation requested in a virtual table's xUpdate, decoded from
+/// its `argv`. A "rowid" here is the PRIMARY KEY for `WITHOUT ROWID` tables.
+/// Its values borrow `argv`, so they can't be kept after xUpdate returns.
+/// <https://www.sqlite.org/vtab.html#xupdate>
 #[derive(Debug)]
 pub enum UpdateOperation<'a> {
-    Delete(&'a *mut sqlite3_value),
+    /// `DELETE` of the row with the given rowid.
+    Delete { rowid: Value<'a> },
+    /// `INSERT` of a new row with the given column values. When `rowid` is
+    /// None, the virtual table picks one itself and writes it to `p_rowid`.
     Insert {
-        values: &'a [*mut sqlite3_value],
-        rowid: Option<&'a *mut sqlite3_value>,
+        rowid: Option<Value<'a>>,
+        values: Vec<Value<'a>>,
     },
+    /// `UPDATE` of the row `old_rowid`, which changes its rowid to `new_rowid`
+    /// (usually the same value) and its columns to `values`. A column is None
+    /// when the UPDATE doesn't change it, and [`crate::api::vtab_nochange`]
+    /// was used to skip it in xColumn.
     Update {
-        _values: &'a [*mut sqlite3_value],
+        old_rowid: Value<'a>,
+        new_rowid: Value<'a>,
+        values: Vec<Option<Value<'a>>>,
     },
 }
 
 fn determine_update_operation<'a>(
     argc: c_int,
     argv: *mut *mut sqlite3_value,
-) -> UpdateOperation<'a> {
-    let args = unsafe { slice::from_raw_parts(argv, argc as usize) };
-
-    // "The value of argc will be 1 for a pure delete operation"
-    if argc == 1 {
-        return UpdateOperation::Delete(
-            args.get(0)
-                .expect("argv[0] should be non-null for DELETE operations"),
-        );
-    }
-
-    let argv0 = args
-        .get(0)
-        .expect("argv[0] should be defined on all non-delete operations");
-    let argv1 = args
-        .get(1)
-        .expect("argv[1] should be defined on all non-delete operations");
-
-    //  argc > 1 AND argv[0] = NULL
-    // "INSERT: A new row is inserted with column values taken from argv[2] and following."
-    if value_type(argv1) == ValueType::Null {
-        let rowid = if value_type(argv0) == ValueType::Null {
-            None
-        } else {
-            Some(argv1)
-        };
-        UpdateOperation::Insert {
-            values: args
-                .get(2..)
-                .expect("argv[0-1] should be defined on INSERT operations"),
-            rowid,
-        }
-    }
-    // argc > 1 AND argv[0] ≠ NULL AND argv[0] = argv[1]
-    // "UPDATE: The row with rowid or PRIMARY KEY argv[0] is updated with new values in argv[2] and following parameters.'
-    else if argv0 == argv1 {
-        UpdateOperation::Update {
-            _values: args
-                .get(2..)
-                .expect("argv[0-1] should be defined on INSERT operations"),
-        }
-    }
-    //argc > 1 AND argv[0] ≠ NULL AND argv[0] ≠ argv[1]
-    // "UPDATE with rowid or PRIMARY KEY change: The row with rowid or PRIMARY KEY argv[0] is updated with
-    // the rowid or PRIMARY KEY in argv[1] and new values in argv[2] and following parameters. "
-    // what the hell does this even mean
-    else if true {
-        todo!();
+) -> Result<UpdateOperation<'a>> {
+    let args = if argc <= 0 || argv.is_null() {
+        &[]
     } else {
-        todo!("some unsupported update operation?")
+        unsafe { slice::from_raw_parts(argv, argc as usize) }
+    };
+
+    match args {
+        // "argc = 1: The single row with rowid or PRIMARY KEY equal to argv[0] is deleted."
+        [argv0] => Ok(UpdateOperation::Delete {
+            rowid: Value::from(argv0)?,
+        }),
+        // "argc > 1, argv[0] = NULL: A new row is inserted with column values taken from
+        // argv[2] and following. [...] If argv[1] is an SQL NULL, then a new unique
+        // rowid is generated automatically."
+        [argv0, argv1, values @ ..] if value_is_null(argv0) => Ok(UpdateOperation::Insert {
+            rowid: if value_is_null(argv1) {
+                None
+            } else {
+                Some(Value::from(argv1)?)
+            },
+            values: values.iter().map(Value::from).collect::<Result<_>>()?,
+        }),
+        // "argc > 1, argv[0] ≠ NULL: The row with rowid or PRIMARY KEY argv[0] is updated
+        // with the rowid or PRIMARY KEY in argv[1] and new values in argv[2] and following."
+        [argv0, argv1, values @ ..] => Ok(UpdateOperation::Update {
+            old_rowid: Value::from(argv0)?,
+            new_rowid: Value::from(argv1)?,
+            values: values
+                .iter()
+                .map(|value| {
+                    if value_nochange(value) {
+                        Ok(None)
+                    } else {
+                        Value::from(value).map(Some)
+                    }
+                })
+                .collect::<Result<_>>()?,
+        }),
+        [] => Err(Error::new_message("xUpdate called without any arguments")),
     }
 }
+
 /// <https://www.sqlite.org/vtab.html#the_xupdate_method>
-// TODO set error message properly
 unsafe extern "C" fn rust_update<'vtab, T: 'vtab>(
     vtab: *mut sqlite3_vtab,
     argc: c_int,
@@ -1095,66 +1725,165 @@ where
 {
     let vt = vtab.cast::<T>();
 
-    match (*vt).update(determine_update_operation(argc, argv), p_rowid) {
+    let result = catch_panic(|| (*vt).update(determine_update_operation(argc, argv)?, p_rowid));
+    match result.and_then(|result| result) {
         Ok(_) => SQLITE_OKAY,
-        Err(err) => err.code(),
+        Err(err) => vtab_error(vtab, err),
     }
 }
 
 /// <https://www.sqlite.org/vtab.html#the_xbegin_method>
-// TODO set error message properly
 unsafe extern "C" fn rust_begin<'vtab, T: 'vtab>(vtab: *mut sqlite3_vtab) -> c_int
 where
     T: VTabWriteableWithTransactions<'vtab>,
 {
     let vt = vtab.cast::<T>();
-    match (*vt).begin() {
+    match catch_panic(|| (*vt).begin()).and_then(|result| result) {
         Ok(_) => SQLITE_OKAY,
-        Err(err) => err.code(),
+        Err(err) => vtab_error(vtab, err),
     }
 }
 
 /// <https://www.sqlite.org/vtab.html#the_xsync_method>
-// TODO set error message properly
 unsafe extern "C" fn rust_sync<'vtab, T: 'vtab>(vtab: *mut sqlite3_vtab) -> c_int
 where
     T: VTabWriteableWithTransactions<'vtab>,
 {
     let vt = vtab.cast::<T>();
-    match (*vt).sync() {
+    match catch_panic(|| (*vt).sync()).and_then(|result| result) {
         Ok(_) => SQLITE_OKAY,
-        Err(err) => err.code(),
+        Err(err) => vtab_error(vtab, err),
     }
 }
 
 /// <https://www.sqlite.org/vtab.html#the_xrollback_method>
-// TODO set error message properly
 unsafe extern "C" fn rust_rollback<'vtab, T: 'vtab>(vtab: *mut sqlite3_vtab) -> c_int
 where
     T: VTabWriteableWithTransactions<'vtab>,
 {
     let vt = vtab.cast::<T>();
-    match (*vt).rollback() {
+    match catch_panic(|| (*vt).rollback()).and_then(|result| result) {
         Ok(_) => SQLITE_OKAY,
-        Err(err) => err.code(),
+        Err(err) => vtab_error(vtab, err),
     }
 }
 
 /// <https://www.sqlite.org/vtab.html#the_xcommit_method>
-// TODO set error message properly
 unsafe extern "C" fn rust_commit<'vtab, T: 'vtab>(vtab: *mut sqlite3_vtab) -> c_int
 where
     T: VTabWriteableWithTransactions<'vtab>,
 {
     let vt = vtab.cast::<T>();
-    match (*vt).commit() {
+    match catch_panic(|| (*vt).commit()).and_then(|result| result) {
         Ok(_) => SQLITE_OKAY,
-        Err(err) => err.code(),
+        Err(err) => vtab_error(vtab, err),
+    }
+}
+
+/// <https://www.sqlite.org/vtab.html#the_xsavepoint_xrelease_and_xrollbackto_methods>
+unsafe extern "C" fn rust_savepoint<'vtab, T: 'vtab>(vtab: *mut sqlite3_vtab, id: c_int) -> c_int
+where
+    T: VTabWriteableNestedTransactions<'vtab>,
+{
+    let vt = vtab.cast::<T>();
+    match catch_panic(|| (*vt).savepoint(id)).and_then(|result| result) {
+        Ok(_) => SQLITE_OKAY,
+        Err(err) => vtab_error(vtab, err),
+    }
+}
+
+/// <https://www.sqlite.org/vtab.html#the_xsavepoint_xrelease_and_xrollbackto_methods>
+unsafe extern "C" fn rust_release<'vtab, T: 'vtab>(vtab: *mut sqlite3_vtab, id: c_int) -> c_int
+where
+    T: VTabWriteableNestedTransactions<'vtab>,
+{
+    let vt = vtab.cast::<T>();
+    match catch_panic(|| (*vt).release(id)).and_then(|result| result) {
+        Ok(_) => SQLITE_OKAY,
+        Err(err) => vtab_error(vtab, err),
+    }
+}
+
+/// <https://www.sqlite.org/vtab.html#the_xsavepoint_xrelease_and_xrollbackto_methods>
+unsafe extern "C" fn rust_rollback_to<'vtab, T: 'vtab>(vtab: *mut sqlite3_vtab, id: c_int) -> c_int
+where
+    T: VTabWriteableNestedTransactions<'vtab>,
+{
+    let vt = vtab.cast::<T>();
+    match catch_panic(|| (*vt).rollback_to(id)).and_then(|result| result) {
+        Ok(_) => SQLITE_OKAY,
+        Err(err) => vtab_error(vtab, err),
+    }
+}
+
+/// <https://www.sqlite.org/vtab.html#the_xrename_method>
+unsafe extern "C" fn rust_rename<'vtab, T: 'vtab>(
+    vtab: *mut sqlite3_vtab,
+    new_name: *const c_char,
+) -> c_int
+where
+    T: VTabRename<'vtab>,
+{
+    let vt = vtab.cast::<T>();
+    let result = catch_panic(|| {
+        let new_name = CStr::from_ptr(new_name).to_str()?;
+        (*vt).rename(new_name)
+    });
+    match result.and_then(|result| result) {
+        Ok(_) => SQLITE_OKAY,
+        Err(err) => vtab_error(vtab, err),
+    }
+}
+
+/// <https://www.sqlite.org/vtab.html#the_xshadowname_method>
+unsafe extern "C" fn rust_shadow_name<'vtab, T: 'vtab>(suffix: *const c_char) -> c_int
+where
+    T: VTabShadowNames<'vtab>,
+{
+    let suffix = CStr::from_ptr(suffix).to_bytes();
+    let is_shadow = catch_panic(|| {
+        T::shadow_names()
+            .iter()
+            .any(|name| name.as_bytes().eq_ignore_ascii_case(suffix))
+    });
+    c_int::from(is_shadow.unwrap_or(false))
+}
+
+/// <https://www.sqlite.org/vtab.html#the_xintegrity_method>
+unsafe extern "C" fn rust_integrity<'vtab, T: 'vtab>(
+    vtab: *mut sqlite3_vtab,
+    schema: *const c_char,
+    table: *const c_char,
+    flags: c_int,
+    err_msg: *mut *mut c_char,
+) -> c_int
+where
+    T: VTabIntegrity<'vtab>,
+{
+    let vt = vtab.cast::<T>();
+    let result = catch_panic(|| {
+        let schema = CStr::from_ptr(schema).to_str()?;
+        let table = CStr::from_ptr(table).to_str()?;
+        (*vt).integrity(schema, table, flags)
+    });
+    match result.and_then(|result| result) {
+        Ok(None) => SQLITE_OKAY,
+        // corruption is reported through pzErr, while still returning SQLITE_OK
+        Ok(Some(problem)) => {
+            let problem = problem.split('\0').next().unwrap_or_default();
+            match mprintf(problem) {
+                Ok(msg) => {
+                    *err_msg = msg;
+                    SQLITE_OKAY
+                }
+                Err(_) => SQLITE_NOMEM,
+            }
+        }
+        Err(err) => report_error(err_msg, err),
     }
 }
 
 /// <https://www.sqlite.org/vtab.html#the_xfindfunction_method>
-// TODO set error message properly
 unsafe extern "C" fn rust_find_function<'vtab, T: 'vtab>(
     vtab: *mut sqlite3_vtab,
     n_arg: c_int,
@@ -1169,7 +1898,8 @@ where
     let name = CStr::from_ptr(name).to_bytes();
     let name = std::str::from_utf8_unchecked(name);
 
-    match (*vt).find_function(n_arg, name) {
+    // xFindFunction can't return errors, so a panic is the same as "not found"
+    match catch_panic(|| (*vt).find_function(n_arg, name)).unwrap_or(None) {
         Some((function, rc, p_arg)) => {
             (*p_xfunc) = Some(function);
             if let Some(p_arg) = p_arg {
@@ -1182,18 +1912,18 @@ where
 }
 
 /// <https://www.sqlite.org/vtab.html#the_xclose_method>
-// TODO set error message properly
 unsafe extern "C" fn rust_close<C>(cursor: *mut sqlite3_vtab_cursor) -> c_int
 where
     C: VTabCursor,
 {
     let cr = cursor.cast::<C>();
-    drop(Box::from_raw(cr));
-    SQLITE_OKAY
+    match catch_panic(|| drop(Box::from_raw(cr))) {
+        Ok(()) => SQLITE_OKAY,
+        Err(err) => err.code_extended() as c_int,
+    }
 }
 
 /// <https://www.sqlite.org/vtab.html#the_xfilter_method>
-// TODO set error message properly
 unsafe extern "C" fn rust_filter<C>(
     cursor: *mut sqlite3_vtab_cursor,
     idx_num: c_int,
@@ -1214,52 +1944,36 @@ where
     let cr = cursor.cast::<C>();
     //cursor_error(cursor, )
     let args = slice::from_raw_parts_mut(argv, argc as usize);
-    match (*cr).filter(idx_num, idx_name, args) {
+    match catch_panic(|| (*cr).filter(idx_num, idx_name, args)).and_then(|result| result) {
         Ok(()) => SQLITE_OKAY,
-        Err(err) => {
-            if let ErrorKind::Message(msg) = err.kind() {
-                if let Ok(err) = mprintf(msg) {
-                    (*(*cursor).pVtab).zErrMsg = err;
-                }
-            };
-            err.code()
-        }
+        Err(err) => cursor_error(cursor, err),
     }
 }
 
 /// <https://www.sqlite.org/vtab.html#the_xnext_method>
-// TODO set error message properly
 unsafe extern "C" fn rust_next<C>(cursor: *mut sqlite3_vtab_cursor) -> c_int
 where
     C: VTabCursor,
 {
     let cr = cursor.cast::<C>();
     //cursor_error(cursor, (*cr).next())
-    match (*cr).next() {
+    match catch_panic(|| (*cr).next()).and_then(|result| result) {
         Ok(()) => SQLITE_OKAY,
-        Err(err) => {
-            if let ErrorKind::Message(msg) = err.kind() {
-                if let Ok(err) = mprintf(msg) {
-                    (*(*cursor).pVtab).zErrMsg = err;
-                }
-            };
-            err.code()
-        }
+        Err(err) => cursor_error(cursor, err),
     }
 }
 
 /// <https://www.sqlite.org/vtab.html#the_xeof_method>
-// TODO set error message properly
 unsafe extern "C" fn rust_eof<C>(cursor: *mut sqlite3_vtab_cursor) -> c_int
 where
     C: VTabCursor,
 {
     let cr = cursor.cast::<C>();
-    (*cr).eof() as c_int
+    // xEof can't return errors, so a panic ends the scan early
+    catch_panic(|| (*cr).eof()).unwrap_or(true) as c_int
 }
 
 /// <https://www.sqlite.org/vtab.html#the_xcolumn_method>
-// TODO set error message properly
 unsafe extern "C" fn rust_column<C>(
     cursor: *mut sqlite3_vtab_cursor,
     ctx: *mut sqlite3_context,
@@ -1270,16 +1984,9 @@ where
 {
     let cr = cursor.cast::<C>();
     //result_error(ctx, (*cr).column(&mut ctxt, i))
-    match (*cr).column(ctx, i) {
+    match catch_panic(|| (*cr).column(ctx, i)).and_then(|result| result) {
         Ok(()) => SQLITE_OKAY,
-        Err(err) => {
-            if let ErrorKind::Message(msg) = err.kind() {
-                if let Ok(err) = mprintf(msg) {
-                    (*(*cursor).pVtab).zErrMsg = err;
-                }
-            };
-            err.code()
-        }
+        Err(err) => cursor_error(cursor, err),
     }
 }
 
@@ -1287,17 +1994,16 @@ where
 /// that the virtual table cursor pCur is currently pointing at.
 /// This method returns SQLITE_OKAY on success. It returns an appropriate error code on failure."
 /// <https://www.sqlite.org/vtab.html#the_xrowid_method>
-// TODO set error message properly
 unsafe extern "C" fn rust_rowid<C>(cursor: *mut sqlite3_vtab_cursor, p_rowid: *mut i64) -> c_int
 where
     C: VTabCursor,
 {
     let cr = cursor.cast::<C>();
-    match (*cr).rowid() {
+    match catch_panic(|| (*cr).rowid()).and_then(|result| result) {
         Ok(rowid) => {
             *p_rowid = rowid;
             SQLITE_OKAY
         }
-        Err(err) => err.code(),
+        Err(err) => cursor_error(cursor, err),
     }
 }
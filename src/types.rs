@@ -0,0 +1,478 @@
+//! Conversions between Rust types and SQLite values, used by
+//! [`define_typed_scalar_function`](crate::scalar::define_typed_scalar_function)
+//! and [`VTabTypedCursor`](crate::table::VTabTypedCursor).
+
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+use crate::{
+    api::{self, ValueType},
+    errors::{Error, Result},
+    ext::{
+        sqlite3_context, sqlite3_value, sqlite3ext_result_blob,
+        sqlite3ext_result_blob_with_destructor, sqlite3ext_result_pointer, sqlite3ext_result_text,
+    },
+};
+use std::{
+    borrow::Cow,
+    ffi::CString,
+    fmt, mem,
+    os::raw::{c_char, c_int, c_void},
+    ptr,
+};
+
+/// A Rust type that can be read out of a [`sqlite3_value`]. Conversions are strict:
+/// a value is only converted if its [`ValueType`] matches what the Rust type expects,
+/// so a `TEXT` value passed into an `i64` argument is an error instead of a silent `0`.
+///
+/// `'a` is how long the value can be borrowed: `&str` and `&[u8]` point into the
+/// `sqlite3_value`, which SQLite frees or converts once the callback returns.
+pub trait FromSqlValue<'a>: Sized {
+    fn from_sql_value(value: &'a *mut sqlite3_value) -> Result<Self>;
+}
+
+/// An argument type of a
+/// [`define_typed_scalar_function`](crate::scalar::define_typed_scalar_function)
+/// function. `Bound<'a>` is the same type with any borrow tied to a single call,
+/// so a function can take `&str` but can't keep it around as `&'static str`.
+pub trait TypedArgument {
+    type Bound<'a>: FromSqlValue<'a>;
+}
+
+/// A Rust type that can be returned from a SQL function, by calling one of
+/// the [`sqlite3_result_*`](https://www.sqlite.org/c3ref/result_blob.html)
+/// functions on the given context.
+pub trait ToSqlResult {
+    fn to_sql_result(self, context: *mut sqlite3_context) -> Result<()>;
+}
+
+fn expect_type(value: &*mut sqlite3_value, expected: &[ValueType]) -> Result<()> {
+    let actual = api::value_type(value);
+    if expected.contains(&actual) {
+        Ok(())
+    } else {
+        Err(Error::new_message(format!(
+            "expected {}, got {}",
+            expected[0], actual
+        )))
+    }
+}
+
+impl FromSqlValue<'_> for i64 {
+    fn from_sql_value(value: &*mut sqlite3_value) -> Result<Self> {
+        expect_type(value, &[ValueType::Integer])?;
+        Ok(api::value_int64(value))
+    }
+}
+
+impl FromSqlValue<'_> for i32 {
+    fn from_sql_value(value: &*mut sqlite3_value) -> Result<Self> {
+        let i = i64::from_sql_value(value)?;
+        i32::try_from(i).map_err(|_| Error::new_message(format!("integer {} out of range", i)))
+    }
+}
+
+impl FromSqlValue<'_> for f64 {
+    fn from_sql_value(value: &*mut sqlite3_value) -> Result<Self> {
+        // integers are accepted as well, since SQL literals like `1` are never REAL
+        expect_type(value, &[ValueType::Float, ValueType::Integer])?;
+        Ok(api::value_double(value))
+    }
+}
+
+impl FromSqlValue<'_> for bool {
+    fn from_sql_value(value: &*mut sqlite3_value) -> Result<Self> {
+        Ok(i64::from_sql_value(value)? != 0)
+    }
+}
+
+impl<'a> FromSqlValue<'a> for &'a str {
+    fn from_sql_value(value: &'a *mut sqlite3_value) -> Result<Self> {
+        expect_type(value, &[ValueType::Text])?;
+        Ok(api::value_text(value)?)
+    }
+}
+
+impl FromSqlValue<'_> for String {
+    fn from_sql_value(value: &*mut sqlite3_value) -> Result<Self> {
+        <&str>::from_sql_value(value).map(str::to_owned)
+    }
+}
+
+impl<'a> FromSqlValue<'a> for &'a [u8] {
+    fn from_sql_value(value: &'a *mut sqlite3_value) -> Result<Self> {
+        expect_type(value, &[ValueType::Blob])?;
+        // zero-length blobs can be NULL pointers, which can't be made into a slice
+        if api::value_bytes(value) == 0 {
+            return Ok(&[]);
+        }
+        Ok(api::value_blob(value))
+    }
+}
+
+impl FromSqlValue<'_> for Vec<u8> {
+    fn from_sql_value(value: &*mut sqlite3_value) -> Result<Self> {
+        <&[u8]>::from_sql_value(value).map(<[u8]>::to_vec)
+    }
+}
+
+impl FromSqlValue<'_> for serde_json::Value {
+    fn from_sql_value(value: &*mut sqlite3_value) -> Result<Self> {
+        match api::value_type(value) {
+            ValueType::Text => serde_json::from_str(api::value_text(value)?)
+                .map_err(|e| Error::new_message(format!("invalid JSON: {}", e))),
+            ValueType::Integer => Ok(api::value_int64(value).into()),
+            ValueType::Float => Ok(api::value_double(value).into()),
+            ValueType::Null => Ok(serde_json::Value::Null),
+            ValueType::Blob => Err(Error::new_message("expected JSON, got blob")),
+        }
+    }
+}
+
+/// `NULL` becomes `None`, every other value is converted with `T`.
+impl<'a, T: FromSqlValue<'a>> FromSqlValue<'a> for Option<T> {
+    fn from_sql_value(value: &'a *mut sqlite3_value) -> Result<Self> {
+        if api::value_is_null(value) {
+            Ok(None)
+        } else {
+            T::from_sql_value(value).map(Some)
+        }
+    }
+}
+
+impl TypedArgument for i64 {
+    type Bound<'a> = i64;
+}
+
+impl TypedArgument for i32 {
+    type Bound<'a> = i32;
+}
+
+impl TypedArgument for f64 {
+    type Bound<'a> = f64;
+}
+
+impl TypedArgument for bool {
+    type Bound<'a> = bool;
+}
+
+impl TypedArgument for &str {
+    type Bound<'a> = &'a str;
+}
+
+impl TypedArgument for String {
+    type Bound<'a> = String;
+}
+
+impl TypedArgument for &[u8] {
+    type Bound<'a> = &'a [u8];
+}
+
+impl TypedArgument for Vec<u8> {
+    type Bound<'a> = Vec<u8>;
+}
+
+impl TypedArgument for serde_json::Value {
+    type Bound<'a> = serde_json::Value;
+}
+
+impl<T: TypedArgument> TypedArgument for Option<T> {
+    type Bound<'a> = Option<T::Bound<'a>>;
+}
+
+impl ToSqlResult for i64 {
+    fn to_sql_result(self, context: *mut sqlite3_context) -> Result<()> {
+        api::result_int64(context, self);
+        Ok(())
+    }
+}
+
+impl ToSqlResult for i32 {
+    fn to_sql_result(self, context: *mut sqlite3_context) -> Result<()> {
+        api::result_int(context, self);
+        Ok(())
+    }
+}
+
+impl ToSqlResult for f64 {
+    fn to_sql_result(self, context: *mut sqlite3_context) -> Result<()> {
+        api::result_double(context, self);
+        Ok(())
+    }
+}
+
+impl ToSqlResult for bool {
+    fn to_sql_result(self, context: *mut sqlite3_context) -> Result<()> {
+        api::result_bool(context, self);
+        Ok(())
+    }
+}
+
+impl ToSqlResult for &str {
+    fn to_sql_result(self, context: *mut sqlite3_context) -> Result<()> {
+        api::result_text(context, self)
+    }
+}
+
+impl ToSqlResult for String {
+    fn to_sql_result(self, context: *mut sqlite3_context) -> Result<()> {
+        api::result_text(context, self)
+    }
+}
+
+impl ToSqlResult for &[u8] {
+    fn to_sql_result(self, context: *mut sqlite3_context) -> Result<()> {
+        api::result_blob(context, self);
+        Ok(())
+    }
+}
+
+impl ToSqlResult for Vec<u8> {
+    fn to_sql_result(self, context: *mut sqlite3_context) -> Result<()> {
+        api::result_blob(context, self.as_slice());
+        Ok(())
+    }
+}
+
+impl ToSqlResult for serde_json::Value {
+    fn to_sql_result(self, context: *mut sqlite3_context) -> Result<()> {
+        api::result_json(context, self)
+    }
+}
+
+/// Functions that don't return anything result in `NULL`.
+impl ToSqlResult for () {
+    fn to_sql_result(self, context: *mut sqlite3_context) -> Result<()> {
+        api::result_null(context);
+        Ok(())
+    }
+}
+
+/// `None` results in `NULL`.
+impl<T: ToSqlResult> ToSqlResult for Option<T> {
+    fn to_sql_result(self, context: *mut sqlite3_context) -> Result<()> {
+        match self {
+            Some(value) => value.to_sql_result(context),
+            None => {
+                api::result_null(context);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// An owned or borrowed SQL value, that can be returned without calling the
+/// [`api`] result functions yourself.
+///
+/// Borrowed text and blobs are copied once by SQLite (`SQLITE_TRANSIENT`), since
+/// SQLite can keep reading a `SQLITE_STATIC` value after the borrow ends, ex.
+/// after a virtual table cursor moves to the next row. Only `'static` data can
+/// be returned without a copy, with [`SqlValue::StaticText`] and
+/// [`SqlValue::StaticBlob`]. Owned text is handed over to SQLite without a copy,
+/// but owned blobs are copied, because their destructor can't be told the
+/// length that Rust needs to free them.
+#[derive(Debug)]
+pub enum SqlValue<'a> {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(Cow<'a, str>),
+    Blob(Cow<'a, [u8]>),
+    /// Text that lives for the whole program, like a string literal, which
+    /// SQLite reads in place (`SQLITE_STATIC`).
+    StaticText(&'static str),
+    /// Same as [`SqlValue::StaticText`], for blobs.
+    StaticBlob(&'static [u8]),
+    /// Text with the JSON subtype, like [`api::result_json`].
+    Json(serde_json::Value),
+    /// A pointer that only [`api::value_pointer`] can read.
+    Pointer(SqlPointer),
+}
+
+/// A Rust object passed around SQL as a [pointer](https://www.sqlite.org/bindptr.html),
+/// for [`SqlValue::Pointer`]. It is dropped by SQLite once it's no longer used,
+/// or here if it's never returned.
+pub struct SqlPointer {
+    name: &'static [u8],
+    pointer: *mut c_void,
+    destroy: unsafe extern "C" fn(*mut c_void),
+}
+
+impl SqlPointer {
+    /// `name` is the pointer type that [`api::value_pointer`] will need, and
+    /// must be a NUL-terminated byte string like `b"my_type\0"`.
+    ///
+    /// # Panics
+    /// If `name` doesn't end with a NUL byte.
+    pub fn new<T>(name: &'static [u8], object: T) -> SqlPointer {
+        assert_eq!(name.last(), Some(&0), "pointer names must end with a NUL");
+        SqlPointer {
+            name,
+            pointer: Box::into_raw(Box::new(object)).cast::<c_void>(),
+            destroy: drop_pointer::<T>,
+        }
+    }
+}
+
+unsafe extern "C" fn drop_pointer<T>(pointer: *mut c_void) {
+    drop(Box::from_raw(pointer.cast::<T>()))
+}
+
+impl Drop for SqlPointer {
+    fn drop(&mut self) {
+        if !self.pointer.is_null() {
+            unsafe { (self.destroy)(self.pointer) }
+        }
+    }
+}
+
+impl fmt::Debug for SqlPointer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = self.name.strip_suffix(&[0]).unwrap_or(self.name);
+        f.debug_tuple("SqlPointer")
+            .field(&String::from_utf8_lossy(name))
+            .finish()
+    }
+}
+
+/// `SQLITE_TRANSIENT`, for SQLite to make its own copy of a value.
+/// <https://www.sqlite.org/c3ref/c_static.html>
+fn sqlite_transient() -> Option<unsafe extern "C" fn(*mut c_void)> {
+    Some(unsafe { mem::transmute::<isize, unsafe extern "C" fn(*mut c_void)>(-1) })
+}
+
+fn value_length(length: usize) -> Result<c_int> {
+    length
+        .try_into()
+        .map_err(|_| Error::new_message("i32 overflow, value too large"))
+}
+
+unsafe extern "C" fn drop_cstring(raw: *mut c_void) {
+    drop(CString::from_raw(raw.cast::<c_char>()));
+}
+
+impl ToSqlResult for SqlValue<'_> {
+    fn to_sql_result(self, context: *mut sqlite3_context) -> Result<()> {
+        match self {
+            SqlValue::Null => api::result_null(context),
+            SqlValue::Integer(i) => api::result_int64(context, i),
+            SqlValue::Real(f) => api::result_double(context, f),
+            // CString::from_raw() finds the length with the first NUL, so text
+            // with NULs inside has to be copied instead
+            SqlValue::Text(Cow::Owned(text)) if !text.as_bytes().contains(&0) => {
+                let n = value_length(text.len())?;
+                let text = unsafe { CString::from_vec_unchecked(text.into_bytes()) };
+                unsafe { sqlite3ext_result_text(context, text.into_raw(), n, Some(drop_cstring)) };
+            }
+            SqlValue::Text(text) => {
+                let n = value_length(text.len())?;
+                unsafe {
+                    sqlite3ext_result_text(
+                        context,
+                        text.as_ptr().cast::<c_char>(),
+                        n,
+                        sqlite_transient(),
+                    )
+                };
+            }
+            SqlValue::Blob(blob) => {
+                let n = value_length(blob.len())?;
+                unsafe { sqlite3ext_result_blob(context, blob.as_ptr().cast::<c_void>(), n) };
+            }
+            // None is SQLITE_STATIC
+            SqlValue::StaticText(text) => {
+                let n = value_length(text.len())?;
+                unsafe { sqlite3ext_result_text(context, text.as_ptr().cast::<c_char>(), n, None) };
+            }
+            SqlValue::StaticBlob(blob) => {
+                let n = value_length(blob.len())?;
+                unsafe {
+                    sqlite3ext_result_blob_with_destructor(
+                        context,
+                        blob.as_ptr().cast::<c_void>(),
+                        n,
+                        None,
+                    )
+                };
+            }
+            SqlValue::Json(json) => api::result_json(context, json)?,
+            SqlValue::Pointer(mut pointer) => {
+                let raw = mem::replace(&mut pointer.pointer, ptr::null_mut());
+                unsafe {
+                    sqlite3ext_result_pointer(
+                        context,
+                        raw,
+                        pointer.name.as_ptr().cast::<c_char>().cast_mut(),
+                        Some(pointer.destroy),
+                    )
+                };
+            }
+        }
+        Ok(())
+    }
+}
+
+impl From<i64> for SqlValue<'_> {
+    fn from(value: i64) -> Self {
+        SqlValue::Integer(value)
+    }
+}
+
+impl From<i32> for SqlValue<'_> {
+    fn from(value: i32) -> Self {
+        SqlValue::Integer(value.into())
+    }
+}
+
+impl From<bool> for SqlValue<'_> {
+    fn from(value: bool) -> Self {
+        SqlValue::Integer(value.into())
+    }
+}
+
+impl From<f64> for SqlValue<'_> {
+    fn from(value: f64) -> Self {
+        SqlValue::Real(value)
+    }
+}
+
+impl<'a> From<&'a str> for SqlValue<'a> {
+    fn from(value: &'a str) -> Self {
+        SqlValue::Text(Cow::Borrowed(value))
+    }
+}
+
+impl From<String> for SqlValue<'_> {
+    fn from(value: String) -> Self {
+        SqlValue::Text(Cow::Owned(value))
+    }
+}
+
+impl<'a> From<&'a [u8]> for SqlValue<'a> {
+    fn from(value: &'a [u8]) -> Self {
+        SqlValue::Blob(Cow::Borrowed(value))
+    }
+}
+
+impl From<Vec<u8>> for SqlValue<'_> {
+    fn from(value: Vec<u8>) -> Self {
+        SqlValue::Blob(Cow::Owned(value))
+    }
+}
+
+impl From<serde_json::Value> for SqlValue<'_> {
+    fn from(value: serde_json::Value) -> Self {
+        SqlValue::Json(value)
+    }
+}
+
+impl From<SqlPointer> for SqlValue<'_> {
+    fn from(value: SqlPointer) -> Self {
+        SqlValue::Pointer(value)
+    }
+}
+
+/// `None` is `NULL`.
+impl<'a, T: Into<SqlValue<'a>>> From<Option<T>> for SqlValue<'a> {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(SqlValue::Null, Into::into)
+    }
+}
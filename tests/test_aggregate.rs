@@ -0,0 +1,118 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{api, define_aggregate_function, Aggregate, Context, Error, Result};
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static CREATED: AtomicUsize = AtomicUsize::new(0);
+static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+// weighted_avg(value, weight)
+pub struct WeightedAvg {
+    total: f64,
+    weights: f64,
+}
+
+impl Default for WeightedAvg {
+    fn default() -> Self {
+        CREATED.fetch_add(1, Ordering::SeqCst);
+        WeightedAvg {
+            total: 0.0,
+            weights: 0.0,
+        }
+    }
+}
+
+impl Drop for WeightedAvg {
+    fn drop(&mut self) {
+        DROPPED.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+impl Aggregate for WeightedAvg {
+    fn step(&mut self, _context: &mut Context, values: &[*mut sqlite3_value]) -> Result<()> {
+        let weight = api::value_double(&values[1]);
+        if weight < 0.0 {
+            return Err(Error::new_message("weights must be positive"));
+        }
+        self.total += api::value_double(&values[0]) * weight;
+        self.weights += weight;
+        Ok(())
+    }
+
+    fn finalize(self, context: &mut Context) -> Result<()> {
+        if self.weights == 0.0 {
+            context.result_null();
+        } else {
+            context.result_double(self.total / self.weights);
+        }
+        Ok(())
+    }
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_aggregate_init(db: *mut sqlite3) -> Result<()> {
+    define_aggregate_function::<WeightedAvg>(db, "weighted_avg", 2, FunctionFlags::UTF8)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(std::mem::transmute(
+                sqlite3_aggregate_init as *const (),
+            )));
+        }
+
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "
+            create table t(grp, value, weight);
+            insert into t values
+              ('a', 1, 1), ('a', 4, 2),
+              ('b', 10, 1), ('b', 20, 1), ('b', 30, 2);
+            ",
+        )
+        .unwrap();
+
+        let result: Vec<(String, f64)> = db
+            .prepare("select grp, weighted_avg(value, weight) from t group by grp order by grp")
+            .unwrap()
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(result, [("a".to_owned(), 3.0), ("b".to_owned(), 22.5)]);
+
+        // no rows at all, finalize runs on a default state
+        let result: Option<f64> = db
+            .query_row(
+                "select weighted_avg(value, weight) from t where 0",
+                [],
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(result, None);
+
+        // an error midway through a group aborts the query
+        db.execute("insert into t values ('c', 1, 1), ('c', 1, -1)", [])
+            .unwrap();
+        let err = db
+            .query_row("select weighted_avg(value, weight) from t", [], |r| {
+                r.get::<_, f64>(0)
+            })
+            .unwrap_err();
+        assert_eq!(err.to_string(), "weights must be positive".to_owned());
+
+        drop(db);
+        assert_eq!(
+            CREATED.load(Ordering::SeqCst),
+            DROPPED.load(Ordering::SeqCst)
+        );
+    }
+}
@@ -0,0 +1,206 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api, define_virtual_table,
+    table::{BestIndexError, ConstraintOperator, IndexInfo, VTab, VTabArguments, VTabCursor},
+    Result,
+};
+
+use std::{mem, os::raw::c_int, sync::Mutex};
+
+/// The (rhs value as text, collation) of every usable constraint of the last
+/// xBestIndex call
+static CONSTRAINTS: Mutex<Vec<(Option<String>, String)>> = Mutex::new(Vec::new());
+
+// create virtual table t using numbers();
+// The numbers 1 to 10 as (value, name) rows, ex (1, 'N1'). Only BINARY
+// equality on "name" is handled by the table itself.
+#[repr(C)]
+pub struct NumbersTable {
+    base: sqlite3_vtab,
+}
+
+impl<'vtab> VTab<'vtab> for NumbersTable {
+    type Aux = ();
+    type Cursor = NumbersCursor;
+
+    fn connect(
+        _db: *mut sqlite3,
+        _aux: Option<&Self::Aux>,
+        _args: VTabArguments,
+    ) -> Result<(String, NumbersTable)> {
+        let base: sqlite3_vtab = unsafe { mem::zeroed() };
+        Ok((
+            "CREATE TABLE x(value integer, name text)".to_owned(),
+            NumbersTable { base },
+        ))
+    }
+
+    fn best_index(&mut self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        let mut constraints = CONSTRAINTS.lock().unwrap();
+        constraints.clear();
+        let mut idx_num = 0;
+        for mut constraint in info.constraints() {
+            if !constraint.usable() {
+                continue;
+            }
+            let collation = constraint.collation().unwrap_or_default().to_owned();
+            constraints.push((
+                constraint
+                    .rhs_value()
+                    .map(|value| api::value_text(&value.as_ptr()).unwrap().to_owned()),
+                collation.clone(),
+            ));
+            if constraint.column_idx() == 1
+                && constraint.op() == Some(ConstraintOperator::EQ)
+                && collation.eq_ignore_ascii_case("BINARY")
+            {
+                constraint.set_argv_index(1);
+                constraint.set_omit(true);
+                idx_num = 1;
+            }
+        }
+        info.set_idxnum(idx_num);
+        info.set_estimated_cost(if idx_num == 1 { 1.0 } else { 10.0 });
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<NumbersCursor> {
+        let base: sqlite3_vtab_cursor = unsafe { mem::zeroed() };
+        Ok(NumbersCursor {
+            base,
+            values: vec![],
+            idx: 0,
+        })
+    }
+}
+
+#[repr(C)]
+pub struct NumbersCursor {
+    base: sqlite3_vtab_cursor,
+    values: Vec<i64>,
+    idx: usize,
+}
+
+impl VTabCursor for NumbersCursor {
+    fn filter(
+        &mut self,
+        idx_num: c_int,
+        _idx_str: Option<&str>,
+        values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        self.values = (1..=10).collect();
+        if idx_num == 1 {
+            let name = api::value_text(&values[0])?;
+            self.values.retain(|value| format!("N{}", value) == name);
+        }
+        self.idx = 0;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.idx += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.idx >= self.values.len()
+    }
+
+    fn column(&self, context: *mut sqlite3_context, i: c_int) -> Result<()> {
+        let value = self.values[self.idx];
+        match i {
+            0 => api::result_int64(context, value),
+            _ => api::result_text(context, format!("N{}", value))?,
+        }
+        Ok(())
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.values[self.idx])
+    }
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_constraintvalues_init(db: *mut sqlite3) -> Result<()> {
+    define_virtual_table::<NumbersTable>(db, "numbers", None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    fn values<P: rusqlite::Params>(db: &Connection, sql: &str, params: P) -> Vec<i64> {
+        db.prepare(sql)
+            .unwrap()
+            .query_map(params, |r| r.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>, _>>()
+            .unwrap()
+    }
+
+    fn constraints() -> Vec<(Option<String>, String)> {
+        CONSTRAINTS.lock().unwrap().clone()
+    }
+
+    /// The expected constraint, given what the runtime SQLite version supports
+    fn constraint(rhs: Option<&str>, collation: &str) -> (Option<String>, String) {
+        let version = rusqlite::version_number();
+        (
+            rhs.filter(|_| version >= 3038000).map(str::to_owned),
+            if version >= 3022000 { collation } else { "" }.to_owned(),
+        )
+    }
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(std::mem::transmute(
+                sqlite3_constraintvalues_init as *const (),
+            )));
+        }
+
+        let db = Connection::open_in_memory().unwrap();
+        db.execute("create virtual table t using numbers()", [])
+            .unwrap();
+
+        // literals are known at planning time, bound parameters aren't
+        assert_eq!(
+            values(
+                &db,
+                "select value from t where value > 8 and value <= ?",
+                [9]
+            ),
+            [9]
+        );
+        assert_eq!(
+            constraints(),
+            [constraint(Some("8"), "BINARY"), constraint(None, "BINARY")]
+        );
+
+        assert_eq!(
+            values(&db, "select value from t where name = 'N3'", []),
+            [3]
+        );
+        assert_eq!(constraints(), [constraint(Some("N3"), "BINARY")]);
+
+        // NOCASE constraints are left for SQLite to check
+        assert_eq!(
+            values(
+                &db,
+                "select value from t where name collate nocase = 'n4'",
+                []
+            ),
+            [4]
+        );
+        assert_eq!(constraints(), [constraint(Some("n4"), "NOCASE")]);
+
+        // expressions aren't evaluated at planning time either
+        assert_eq!(
+            values(&db, "select value from t where value = abs(-5)", []),
+            [5]
+        );
+        assert_eq!(constraints(), [constraint(None, "BINARY")]);
+    }
+}
@@ -0,0 +1,121 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{api, define_scalar_function_with_context, Context, Result};
+
+use std::{
+    os::raw::c_void,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+static PREFIXES_BUILT: AtomicUsize = AtomicUsize::new(0);
+
+// upper(text)
+fn upper(context: &mut Context, values: &[*mut sqlite3_value]) -> Result<()> {
+    context.result_text(api::value_text(&values[0])?.to_uppercase())
+}
+
+// words(text)
+fn words(context: &mut Context, values: &[*mut sqlite3_value]) -> Result<()> {
+    let words: Vec<&str> = api::value_text(&values[0])?.split_whitespace().collect();
+    context.result_json(serde_json::json!(words))
+}
+
+// with_prefix(prefix, text)
+// The formatted prefix is cached as auxdata for the 1st argument, so it's
+// only built once per statement when the prefix is constant.
+fn with_prefix(context: &mut Context, values: &[*mut sqlite3_value]) -> Result<()> {
+    if context.get_auxdata::<String>(0).is_none() {
+        PREFIXES_BUILT.fetch_add(1, Ordering::SeqCst);
+        let prefix = format!("{}: ", api::value_text(&values[0])?);
+        context.set_auxdata(0, prefix);
+    }
+    // auxdata is only read back as the type it was stored as
+    if context.get_auxdata::<i64>(0).is_some() {
+        return Err("auxdata read as the wrong type".into());
+    }
+    let text = api::value_text(&values[1])?;
+    let result = match context.get_auxdata::<String>(0) {
+        Some(prefix) => format!("{}{}", prefix, text),
+        // SQLite is allowed to discard auxdata right away
+        None => format!("{}: {}", api::value_text(&values[0])?, text),
+    };
+    context.result_text(result)
+}
+
+unsafe extern "C" fn drop_bytes(p: *mut c_void) {
+    drop(Box::from_raw(p.cast::<[u8; 32]>()));
+}
+
+// raw_auxdata(x)
+// Stores auxdata with the raw API, which get_auxdata() must not read as its own.
+fn raw_auxdata(context: &mut Context, _values: &[*mut sqlite3_value]) -> Result<()> {
+    let found = context.get_auxdata::<[u8; 32]>(0).is_some();
+    let data = Box::into_raw(Box::new([7_u8; 32])).cast::<c_void>();
+    api::auxdata_set(context.as_ptr(), 0, data, Some(drop_bytes));
+    context.result(found)
+}
+
+// has_db()
+fn has_db(context: &mut Context, _values: &[*mut sqlite3_value]) -> Result<()> {
+    let has_db = !context.db_handle().is_null();
+    context.result(has_db)
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_context_init(db: *mut sqlite3) -> Result<()> {
+    let flags = FunctionFlags::UTF8 | FunctionFlags::DETERMINISTIC;
+    define_scalar_function_with_context(db, "upper_ctx", 1, upper, flags)?;
+    define_scalar_function_with_context(db, "words", 1, words, flags)?;
+    define_scalar_function_with_context(db, "with_prefix", 2, with_prefix, flags)?;
+    define_scalar_function_with_context(db, "raw_auxdata", 1, raw_auxdata, flags)?;
+    define_scalar_function_with_context(db, "has_db", 0, has_db, flags)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(std::mem::transmute(sqlite3_context_init as *const ())));
+        }
+
+        let db = Connection::open_in_memory().unwrap();
+
+        let result: String = db
+            .query_row("select upper_ctx('alex')", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(result, "ALEX");
+
+        // the 'J' subtype makes other JSON functions treat the result as JSON
+        let result: String = db
+            .query_row("select json_array(words('a b  c'))", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(result, r#"[["a","b","c"]]"#);
+
+        let result: Vec<String> = db
+            .prepare("select with_prefix('log', value) from json_each('[\"a\", \"b\", \"c\"]')")
+            .unwrap()
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(result, ["log: a", "log: b", "log: c"]);
+        assert_eq!(PREFIXES_BUILT.load(Ordering::SeqCst), 1);
+
+        let result: Vec<bool> = db
+            .prepare("select raw_auxdata(1) from json_each('[1, 2, 3]')")
+            .unwrap()
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(result, [false, false, false]);
+
+        let result: bool = db.query_row("select has_db()", [], |r| r.get(0)).unwrap();
+        assert!(result);
+    }
+}
@@ -0,0 +1,97 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api, define_collation, define_scalar_function, define_scalar_function_with_aux, Result,
+};
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+/// Stand-in for state captured by a function, like a connection pool or a cache.
+struct Resource(i64);
+
+impl Resource {
+    fn value(&self) -> i64 {
+        self.0
+    }
+}
+
+impl Drop for Resource {
+    fn drop(&mut self) {
+        DROPPED.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+fn define_functions(db: *mut sqlite3) -> Result<()> {
+    let captured = Resource(1);
+    define_scalar_function(
+        db,
+        "captured",
+        0,
+        move |context, _values| {
+            api::result_int64(context, captured.value());
+            Ok(())
+        },
+        FunctionFlags::UTF8,
+    )?;
+    define_scalar_function_with_aux(
+        db,
+        "aux",
+        0,
+        |context, _values, aux: &Resource| {
+            api::result_int64(context, aux.value());
+            Ok(())
+        },
+        FunctionFlags::UTF8,
+        Resource(2),
+    )?;
+    let reverse = Resource(1);
+    define_collation(db, "reverse", move |a, b| {
+        (reverse.value() * b.cmp(a) as i64) as i32
+    })?;
+    Ok(())
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_destroy_init(db: *mut sqlite3) -> Result<()> {
+    define_functions(db)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(std::mem::transmute(sqlite3_destroy_init as *const ())));
+        }
+
+        let db = Connection::open_in_memory().unwrap();
+        let result: (i64, i64) = db
+            .query_row("select captured(), aux()", [], |r| {
+                Ok((r.get(0)?, r.get(1)?))
+            })
+            .unwrap();
+        assert_eq!(result, (1, 2));
+        let result: Vec<String> = db
+            .prepare("select value from json_each('[\"a\", \"c\", \"b\"]') order by value collate reverse")
+            .unwrap()
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(result, ["c", "b", "a"]);
+        assert_eq!(DROPPED.load(Ordering::SeqCst), 0);
+
+        // redefining a function destroys the previous definition
+        define_functions(unsafe { db.handle() }.cast()).unwrap();
+        assert_eq!(DROPPED.load(Ordering::SeqCst), 3);
+
+        // closing the connection destroys everything that's left
+        drop(db);
+        assert_eq!(DROPPED.load(Ordering::SeqCst), 6);
+    }
+}
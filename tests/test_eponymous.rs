@@ -0,0 +1,197 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api,
+    table::{BestIndexError, IndexInfo, VTab, VTabArguments, VTabCursor},
+    ModuleBuilder, Result,
+};
+
+use std::{mem, os::raw::c_int, sync::Mutex};
+
+/// Every lifecycle method called on a LifecycleTable, with its table name
+static EVENTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+fn log_event(event: &str, table: &str) {
+    EVENTS.lock().unwrap().push(format!("{} {}", event, table));
+}
+
+// select value from xxx;
+// A single row table, that logs when it's created, connected, destroyed and
+// dropped (disconnected).
+#[repr(C)]
+pub struct LifecycleTable {
+    base: sqlite3_vtab,
+    name: String,
+}
+
+impl Drop for LifecycleTable {
+    fn drop(&mut self) {
+        log_event("drop", &self.name);
+    }
+}
+
+impl<'vtab> VTab<'vtab> for LifecycleTable {
+    type Aux = ();
+    type Cursor = LifecycleCursor;
+
+    fn create(
+        _db: *mut sqlite3,
+        _aux: Option<&Self::Aux>,
+        args: VTabArguments,
+    ) -> Result<(String, LifecycleTable)> {
+        log_event("create", &args.table_name);
+        let base: sqlite3_vtab = unsafe { mem::zeroed() };
+        let vtab = LifecycleTable {
+            base,
+            name: args.table_name,
+        };
+        Ok(("CREATE TABLE x(value)".to_owned(), vtab))
+    }
+
+    fn connect(
+        _db: *mut sqlite3,
+        _aux: Option<&Self::Aux>,
+        args: VTabArguments,
+    ) -> Result<(String, LifecycleTable)> {
+        log_event("connect", &args.table_name);
+        let base: sqlite3_vtab = unsafe { mem::zeroed() };
+        let vtab = LifecycleTable {
+            base,
+            name: args.table_name,
+        };
+        Ok(("CREATE TABLE x(value)".to_owned(), vtab))
+    }
+
+    fn destroy(&mut self) -> Result<()> {
+        log_event("destroy", &self.name);
+        Ok(())
+    }
+
+    fn best_index(&mut self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        info.set_estimated_cost(1.0);
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<LifecycleCursor> {
+        let base: sqlite3_vtab_cursor = unsafe { mem::zeroed() };
+        Ok(LifecycleCursor { base, rowid: 0 })
+    }
+}
+
+#[repr(C)]
+pub struct LifecycleCursor {
+    base: sqlite3_vtab_cursor,
+    rowid: i64,
+}
+
+impl VTabCursor for LifecycleCursor {
+    fn filter(
+        &mut self,
+        _idx_num: c_int,
+        _idx_str: Option<&str>,
+        _values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        self.rowid = 1;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.rowid += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.rowid > 1
+    }
+
+    fn column(&self, context: *mut sqlite3_context, _i: c_int) -> Result<()> {
+        api::result_int64(context, self.rowid);
+        Ok(())
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.rowid)
+    }
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_eponymous_init(db: *mut sqlite3) -> Result<()> {
+    ModuleBuilder::<LifecycleTable>::new("regular").register(db)?;
+    ModuleBuilder::<LifecycleTable>::new("eponymous")
+        .eponymous()
+        .register(db)?;
+    ModuleBuilder::<LifecycleTable>::new("eponymous_only")
+        .eponymous_only()
+        .register(db)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    fn events() -> Vec<String> {
+        mem::take(&mut *EVENTS.lock().unwrap())
+    }
+
+    fn select(db: &Connection, table: &str) -> rusqlite::Result<i64> {
+        db.query_row(&format!("select value from {}", table), [], |r| r.get(0))
+    }
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(std::mem::transmute(
+                sqlite3_eponymous_init as *const (),
+            )));
+        }
+
+        // regular modules need a CREATE VIRTUAL TABLE, and are destroyed on DROP
+        let db = Connection::open_in_memory().unwrap();
+        assert_eq!(
+            select(&db, "regular").unwrap_err().to_string(),
+            "no such table: regular"
+        );
+        db.execute("create virtual table r using regular()", [])
+            .unwrap();
+        assert_eq!(select(&db, "r").unwrap(), 1);
+        assert_eq!(events(), ["create r"]);
+        db.execute("drop table r", []).unwrap();
+        assert_eq!(events(), ["destroy r", "drop r"]);
+        drop(db);
+        assert!(events().is_empty());
+
+        // eponymous modules work both ways, but only created tables are destroyed
+        let db = Connection::open_in_memory().unwrap();
+        assert_eq!(select(&db, "eponymous").unwrap(), 1);
+        assert_eq!(events(), ["connect eponymous"]);
+        db.execute("create virtual table e using eponymous()", [])
+            .unwrap();
+        assert_eq!(select(&db, "e").unwrap(), 1);
+        assert_eq!(events(), ["connect e"]);
+        assert_eq!(
+            db.execute("drop table eponymous", [])
+                .unwrap_err()
+                .to_string(),
+            "table eponymous may not be dropped"
+        );
+        db.execute("drop table e", []).unwrap();
+        assert_eq!(events(), ["destroy e", "drop e"]);
+        drop(db);
+        assert_eq!(events(), ["drop eponymous"]);
+
+        // eponymous-only modules can't be created at all
+        let db = Connection::open_in_memory().unwrap();
+        assert_eq!(select(&db, "eponymous_only").unwrap(), 1);
+        assert_eq!(events(), ["connect eponymous_only"]);
+        assert_eq!(
+            db.execute("create virtual table o using eponymous_only()", [])
+                .unwrap_err()
+                .to_string(),
+            "no such module: eponymous_only"
+        );
+        drop(db);
+        assert_eq!(events(), ["drop eponymous_only"]);
+    }
+}
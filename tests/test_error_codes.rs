@@ -0,0 +1,81 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    define_scalar_function, Error, Result, SQLITE_CONSTRAINT_UNIQUE, SQLITE_TOOBIG,
+};
+
+// fails(kind) - returns an error of the given kind
+fn fails(_context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    let kind = sqlite_loadable::api::value_text(&values[0])?;
+    Err(match kind {
+        "unique" => Error::new_code(SQLITE_CONSTRAINT_UNIQUE, "key already exists"),
+        "toobig" => Error::new_code(SQLITE_TOOBIG, "value is too large"),
+        "io" => Error::external(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "data.csv not found",
+        )),
+        _ => Error::new_message("plain error"),
+    })
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_errorcodes_init(db: *mut sqlite3) -> Result<()> {
+    define_scalar_function(db, "fails", 1, fails, FunctionFlags::UTF8)?;
+    Ok(())
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_brokenext_init(_db: *mut sqlite3) -> Result<()> {
+    Err(Error::new_message("missing configuration"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{
+        ffi::{sqlite3_auto_extension, sqlite3_cancel_auto_extension},
+        Connection, ErrorCode,
+    };
+
+    fn query_error(db: &Connection, sql: &str) -> rusqlite::Error {
+        db.query_row(sql, [], |r| r.get::<_, i64>(0)).unwrap_err()
+    }
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(std::mem::transmute(
+                sqlite3_errorcodes_init as *const (),
+            )));
+        }
+
+        let db = Connection::open_in_memory().unwrap();
+
+        match query_error(&db, "select fails('unique')") {
+            rusqlite::Error::SqliteFailure(err, Some(msg)) => {
+                assert_eq!(err.code, ErrorCode::ConstraintViolation);
+                assert_eq!(err.extended_code, SQLITE_CONSTRAINT_UNIQUE);
+                assert_eq!(msg, "key already exists");
+            }
+            err => panic!("unexpected error {:?}", err),
+        }
+
+        let err = query_error(&db, "select fails('toobig')");
+        assert_eq!(err.sqlite_error_code(), Some(ErrorCode::TooBig));
+        assert_eq!(err.to_string(), "value is too large");
+
+        let err = query_error(&db, "select fails('io')");
+        assert_eq!(err.sqlite_error_code(), Some(ErrorCode::Unknown));
+        assert_eq!(err.to_string(), "data.csv not found");
+
+        let err = query_error(&db, "select fails('other')");
+        assert_eq!(err.to_string(), "plain error");
+
+        // entrypoint errors are reported with their message
+        let broken = unsafe { std::mem::transmute(sqlite3_brokenext_init as *const ()) };
+        unsafe { sqlite3_auto_extension(Some(broken)) };
+        let err = Connection::open_in_memory().unwrap_err();
+        assert!(err.to_string().contains("missing configuration"), "{}", err);
+        unsafe { sqlite3_cancel_auto_extension(Some(broken)) };
+    }
+}
@@ -45,11 +45,11 @@ impl<'vtab> VTab<'vtab> for FindTable {
         // TODO db.config(VTabConfig::Innocuous)?;
         Ok((CREATE_SQL.to_owned(), vtab))
     }
-    fn destroy(&self) -> Result<()> {
+    fn destroy(&mut self) -> Result<()> {
         Ok(())
     }
 
-    fn best_index(&self, mut _info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+    fn best_index(&mut self, mut _info: IndexInfo) -> core::result::Result<(), BestIndexError> {
         Ok(())
     }
 
@@ -0,0 +1,187 @@
+use serde::{Deserialize, Serialize};
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api, define_table_function,
+    table::{
+        BestIndexError, ConstraintOperator, InValues, IndexInfo, Plan, VTab, VTabArguments,
+        VTabCursor,
+    },
+    Result,
+};
+
+use std::{mem, os::raw::c_int, sync::Mutex};
+
+/// The ids that each xFilter call looked up
+static LOOKUPS: Mutex<Vec<Vec<i64>>> = Mutex::new(Vec::new());
+
+#[derive(Serialize, Deserialize)]
+struct Lookup {
+    by_id: bool,
+    all_in: bool,
+}
+
+// select id, name from users where id in (1, 2);
+// Looks up users by id, with every id of an IN (...) handled in one filter call
+// when SQLite supports it.
+#[repr(C)]
+pub struct UsersTable {
+    base: sqlite3_vtab,
+}
+
+impl<'vtab> VTab<'vtab> for UsersTable {
+    type Aux = ();
+    type Cursor = UsersCursor;
+
+    fn connect(
+        _db: *mut sqlite3,
+        _aux: Option<&Self::Aux>,
+        _args: VTabArguments,
+    ) -> Result<(String, UsersTable)> {
+        let base: sqlite3_vtab = unsafe { mem::zeroed() };
+        Ok(("CREATE TABLE x(id, name)".to_owned(), UsersTable { base }))
+    }
+
+    fn best_index(&mut self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        let mut plan = Plan::new(Lookup {
+            by_id: false,
+            all_in: false,
+        });
+        for mut constraint in info.constraints() {
+            if constraint.usable()
+                && constraint.column_idx() == 0
+                && constraint.op() == Some(ConstraintOperator::EQ)
+                && !plan.plan.by_id
+            {
+                plan.argument(&mut constraint, "id");
+                constraint.set_omit(true);
+                plan.plan.by_id = true;
+                plan.plan.all_in = constraint.enable_process_all_in();
+            }
+        }
+        info.set_estimated_cost(if plan.plan.by_id { 1.0 } else { 1000.0 });
+        info.set_plan(&plan).map_err(|_| BestIndexError::Error)?;
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<UsersCursor> {
+        let base: sqlite3_vtab_cursor = unsafe { mem::zeroed() };
+        Ok(UsersCursor {
+            base,
+            ids: vec![],
+            idx: 0,
+        })
+    }
+}
+
+#[repr(C)]
+pub struct UsersCursor {
+    base: sqlite3_vtab_cursor,
+    ids: Vec<i64>,
+    idx: usize,
+}
+
+impl VTabCursor for UsersCursor {
+    fn filter(
+        &mut self,
+        _idx_num: c_int,
+        idx_str: Option<&str>,
+        values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        let (lookup, arguments) = Plan::<Lookup>::decode(idx_str, values)?;
+        self.ids = match arguments.get("id") {
+            Some(id) => {
+                InValues::<i64>::from_argument(id.as_ptr(), lookup.all_in).collect::<Result<_>>()?
+            }
+            None => (1..=5).collect(),
+        };
+        self.ids.retain(|id| (1..=5).contains(id));
+        LOOKUPS.lock().unwrap().push(self.ids.clone());
+        self.idx = 0;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.idx += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.idx >= self.ids.len()
+    }
+
+    fn column(&self, context: *mut sqlite3_context, i: c_int) -> Result<()> {
+        let id = self.ids[self.idx];
+        match i {
+            0 => api::result_int64(context, id),
+            _ => api::result_text(context, format!("user{}", id))?,
+        }
+        Ok(())
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.ids[self.idx])
+    }
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_invalues_init(db: *mut sqlite3) -> Result<()> {
+    define_table_function::<UsersTable>(db, "users", None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    fn names(db: &Connection, sql: &str) -> rusqlite::Result<Vec<String>> {
+        db.prepare(sql)?
+            .query_map([], |r| r.get(0))?
+            .collect::<rusqlite::Result<Vec<_>, _>>()
+    }
+
+    fn lookups() -> Vec<Vec<i64>> {
+        mem::take(&mut *LOOKUPS.lock().unwrap())
+    }
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(std::mem::transmute(
+                sqlite3_invalues_init as *const (),
+            )));
+        }
+
+        let db = Connection::open_in_memory().unwrap();
+
+        assert_eq!(
+            names(
+                &db,
+                "select name from users where id in (4, 2, 9) order by id"
+            )
+            .unwrap(),
+            ["user2", "user4"]
+        );
+        if rusqlite::version_number() >= 3038000 {
+            // one filter call for the whole list
+            assert_eq!(lookups(), [vec![2, 4]]);
+        } else {
+            assert_eq!(lookups(), [vec![2], vec![4], vec![]]);
+        }
+
+        // a plain = is a single value
+        assert_eq!(
+            names(&db, "select name from users where id = 3").unwrap(),
+            ["user3"]
+        );
+        assert_eq!(lookups(), [vec![3]]);
+
+        // values that aren't integers are errors, not silently 0
+        assert_eq!(
+            names(&db, "select name from users where id in (1, 'a')")
+                .unwrap_err()
+                .to_string(),
+            "expected integer, got text"
+        );
+    }
+}
@@ -0,0 +1,210 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api, define_virtual_table,
+    table::{
+        BestIndexError, ConstraintOperator, Distinct, IndexInfo, IndexScanFlags, OrderByDirection,
+        VTab, VTabArguments, VTabCursor,
+    },
+    Result,
+};
+
+use std::{mem, os::raw::c_int, sync::Mutex};
+
+/// What the last xBestIndex call saw: (distinct, used columns)
+static PLANNED: Mutex<Option<(Distinct, Vec<i32>)>> = Mutex::new(None);
+
+const COLUMNS: i32 = 70;
+const ROWS: i64 = 5;
+
+// create virtual table t using sorted();
+// The numbers 1 to 5 in a "value" column, already sorted, with 69 more padding
+// columns "c1" to "c69" that repeat the value.
+#[repr(C)]
+pub struct SortedTable {
+    base: sqlite3_vtab,
+}
+
+impl<'vtab> VTab<'vtab> for SortedTable {
+    type Aux = ();
+    type Cursor = SortedCursor;
+
+    fn connect(
+        _db: *mut sqlite3,
+        _aux: Option<&Self::Aux>,
+        _args: VTabArguments,
+    ) -> Result<(String, SortedTable)> {
+        let padding: Vec<String> = (1..COLUMNS).map(|i| format!("c{}", i)).collect();
+        let base: sqlite3_vtab = unsafe { mem::zeroed() };
+        Ok((
+            format!("CREATE TABLE x(value, {})", padding.join(", ")),
+            SortedTable { base },
+        ))
+    }
+
+    fn best_index(&mut self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        let mut idx_num = 0;
+        let mut cost = 100.0;
+        for mut constraint in info.constraints() {
+            if constraint.usable()
+                && constraint.column_idx() == 0
+                && constraint.op() == Some(ConstraintOperator::EQ)
+            {
+                constraint.set_argv_index(1);
+                constraint.set_omit(true);
+                idx_num = 1;
+                cost = 1.0;
+            }
+        }
+        if idx_num == 1 {
+            info.set_idx_flags(IndexScanFlags::UNIQUE);
+        }
+
+        // rows are stored in order, so ORDER BY value is free in either direction
+        let order_bys = info.order_bys();
+        if let [order_by] = order_bys.as_slice() {
+            if order_by.icolumn() == 0 {
+                if let OrderByDirection::Descending = order_by.direction() {
+                    idx_num |= 2;
+                }
+                info.set_order_by_consumed(true);
+            }
+        }
+
+        let columns_used = info.columns_used();
+        *PLANNED.lock().unwrap() = Some((
+            info.distinct(),
+            (0..COLUMNS).filter(|&i| columns_used.contains(i)).collect(),
+        ));
+        info.set_idxnum(idx_num);
+        info.set_estimated_cost(cost);
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<SortedCursor> {
+        let base: sqlite3_vtab_cursor = unsafe { mem::zeroed() };
+        Ok(SortedCursor {
+            base,
+            values: vec![],
+            idx: 0,
+        })
+    }
+}
+
+#[repr(C)]
+pub struct SortedCursor {
+    base: sqlite3_vtab_cursor,
+    values: Vec<i64>,
+    idx: usize,
+}
+
+impl VTabCursor for SortedCursor {
+    fn filter(
+        &mut self,
+        idx_num: c_int,
+        _idx_str: Option<&str>,
+        values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        self.values = if idx_num & 1 != 0 {
+            let value = api::value_int64(&values[0]);
+            (1..=ROWS).filter(|&v| v == value).collect()
+        } else {
+            (1..=ROWS).collect()
+        };
+        if idx_num & 2 != 0 {
+            self.values.reverse();
+        }
+        self.idx = 0;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.idx += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.idx >= self.values.len()
+    }
+
+    fn column(&self, context: *mut sqlite3_context, _i: c_int) -> Result<()> {
+        api::result_int64(context, self.values[self.idx]);
+        Ok(())
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.values[self.idx])
+    }
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_indexinfo_init(db: *mut sqlite3) -> Result<()> {
+    define_virtual_table::<SortedTable>(db, "sorted", None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    fn values(db: &Connection, sql: &str) -> Vec<i64> {
+        db.prepare(sql)
+            .unwrap()
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>, _>>()
+            .unwrap()
+    }
+
+    fn plan(db: &Connection, sql: &str) -> String {
+        db.prepare(&format!("explain query plan {}", sql))
+            .unwrap()
+            .query_map([], |r| r.get::<_, String>(3))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>, _>>()
+            .unwrap()
+            .join("\n")
+    }
+
+    fn planned() -> (Distinct, Vec<i32>) {
+        PLANNED.lock().unwrap().take().unwrap()
+    }
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(std::mem::transmute(
+                sqlite3_indexinfo_init as *const (),
+            )));
+        }
+
+        let db = Connection::open_in_memory().unwrap();
+        db.execute("create virtual table t using sorted()", [])
+            .unwrap();
+
+        // the table's own order is used, without a temp b-tree to sort
+        let sql = "select value from t order by value desc";
+        assert_eq!(values(&db, sql), [5, 4, 3, 2, 1]);
+        assert_eq!(planned(), (Distinct::Ordered, vec![0]));
+        assert!(!plan(&db, sql).contains("USE TEMP B-TREE"));
+        assert_eq!(
+            values(&db, "select value from t order by value"),
+            [1, 2, 3, 4, 5]
+        );
+
+        assert_eq!(values(&db, "select value from t where value = 3"), [3]);
+
+        // columns past the 63rd all share the last bit
+        values(&db, "select c1, c64 from t");
+        let (_, used) = planned();
+        assert_eq!(&used[..2], [1, 63]);
+        assert_eq!(used.len(), 2 + (COLUMNS - 64) as usize);
+
+        values(&db, "select value from t group by value");
+        assert_eq!(planned().0, Distinct::Grouped);
+        values(&db, "select distinct value from t");
+        assert_eq!(planned().0, Distinct::Distinct);
+        values(&db, "select distinct value from t order by value");
+        assert_eq!(planned().0, Distinct::DistinctOrdered);
+    }
+}
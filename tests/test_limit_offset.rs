@@ -0,0 +1,199 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api, define_table_function,
+    table::{
+        BestIndexError, ConstraintOperator, IndexInfo, LimitOffset, OrderByDirection, Plan, VTab,
+        VTabArguments, VTabCursor,
+    },
+    Result,
+};
+
+use std::{mem, os::raw::c_int, sync::Mutex};
+
+/// The LIMIT/OFFSET and number of rows read by each xFilter call
+static SCANS: Mutex<Vec<(LimitOffset, i64)>> = Mutex::new(Vec::new());
+
+const LINES: i64 = 100;
+
+// select line from lines limit 10 offset 5;
+// Pretends to read lines 1 to 100 of a remote file, and stops reading once the
+// query's LIMIT is reached.
+#[repr(C)]
+pub struct LinesTable {
+    base: sqlite3_vtab,
+}
+
+impl<'vtab> VTab<'vtab> for LinesTable {
+    type Aux = ();
+    type Cursor = LinesCursor;
+
+    fn connect(
+        _db: *mut sqlite3,
+        _aux: Option<&Self::Aux>,
+        _args: VTabArguments,
+    ) -> Result<(String, LinesTable)> {
+        let base: sqlite3_vtab = unsafe { mem::zeroed() };
+        Ok((
+            "CREATE TABLE x(line, file hidden)".to_owned(),
+            LinesTable { base },
+        ))
+    }
+
+    fn best_index(&mut self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        let mut plan = Plan::new(());
+        for mut constraint in info.constraints() {
+            // the file name is the only constraint handled by the table
+            if constraint.usable()
+                && constraint.column_idx() == 1
+                && constraint.op() == Some(ConstraintOperator::EQ)
+            {
+                plan.argument(&mut constraint, "file");
+                constraint.set_omit(true);
+            }
+        }
+        // lines are read in order
+        if let [order_by] = info.order_bys().as_slice() {
+            if order_by.icolumn() == 0 {
+                if let OrderByDirection::Ascending = order_by.direction() {
+                    info.set_order_by_consumed(true);
+                }
+            }
+        }
+        plan.limit_offset(&info);
+        info.set_plan(&plan).map_err(|_| BestIndexError::Error)?;
+        info.set_estimated_cost(LINES as f64);
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<LinesCursor> {
+        let base: sqlite3_vtab_cursor = unsafe { mem::zeroed() };
+        Ok(LinesCursor {
+            base,
+            line: 0,
+            end: 0,
+        })
+    }
+}
+
+#[repr(C)]
+pub struct LinesCursor {
+    base: sqlite3_vtab_cursor,
+    line: i64,
+    end: i64,
+}
+
+impl VTabCursor for LinesCursor {
+    fn filter(
+        &mut self,
+        _idx_num: c_int,
+        idx_str: Option<&str>,
+        values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        let (_, arguments) = Plan::<()>::decode(idx_str, values)?;
+        let limit_offset = arguments.limit_offset()?;
+        let start = 1 + limit_offset.offset.unwrap_or(0);
+        self.line = start;
+        self.end = match limit_offset.limit {
+            Some(limit) => (start + limit).min(LINES + 1),
+            None => LINES + 1,
+        };
+        SCANS
+            .lock()
+            .unwrap()
+            .push((limit_offset, (self.end - start).max(0)));
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.line += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.line >= self.end
+    }
+
+    fn column(&self, context: *mut sqlite3_context, i: c_int) -> Result<()> {
+        match i {
+            0 => api::result_int64(context, self.line),
+            _ => api::result_null(context),
+        }
+        Ok(())
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.line)
+    }
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_limitoffset_init(db: *mut sqlite3) -> Result<()> {
+    define_table_function::<LinesTable>(db, "lines", None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    fn lines(db: &Connection, sql: &str) -> Vec<i64> {
+        db.prepare(sql)
+            .unwrap()
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>, _>>()
+            .unwrap()
+    }
+
+    fn scans() -> Vec<(LimitOffset, i64)> {
+        mem::take(&mut *SCANS.lock().unwrap())
+    }
+
+    fn scan(limit: Option<i64>, offset: Option<i64>, read: i64) -> (LimitOffset, i64) {
+        (LimitOffset { limit, offset }, read)
+    }
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(std::mem::transmute(
+                sqlite3_limitoffset_init as *const (),
+            )));
+        }
+
+        let db = Connection::open_in_memory().unwrap();
+
+        assert_eq!(lines(&db, "select line from lines limit 3"), [1, 2, 3]);
+        assert_eq!(scans(), [scan(Some(3), None, 3)]);
+
+        assert_eq!(
+            lines(
+                &db,
+                "select line from lines('a.txt') order by line limit 2 offset 5"
+            ),
+            [6, 7]
+        );
+        assert_eq!(scans(), [scan(Some(2), Some(5), 2)]);
+
+        assert_eq!(
+            lines(&db, "select line from lines limit -1 offset 98"),
+            [99, 100]
+        );
+        assert_eq!(scans(), [scan(None, Some(98), 2)]);
+
+        // SQLite filters "line > 50" after the table, so LIMIT can't be pushed down
+        assert_eq!(
+            lines(&db, "select line from lines where line > 50 limit 2"),
+            [51, 52]
+        );
+        assert_eq!(scans(), [scan(None, None, LINES)]);
+
+        // and neither when SQLite has to sort the rows
+        assert_eq!(
+            lines(&db, "select line from lines order by line desc limit 2"),
+            [100, 99]
+        );
+        assert_eq!(scans(), [scan(None, None, LINES)]);
+    }
+}
@@ -0,0 +1,224 @@
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api, define_scalar_function,
+    scalar::scalar_function_raw,
+    table::{
+        BestIndexError, FindResult, IndexInfo, UpdateOperation, VTab, VTabArguments, VTabCursor,
+        VTabFind, VTabWriteable, VTabWriteableWithTransactions,
+    },
+    ModuleBuilder, Result,
+};
+
+use std::{mem, os::raw::c_int, sync::Mutex};
+
+static EVENTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+fn log_event(event: &str) {
+    EVENTS.lock().unwrap().push(event.to_owned());
+}
+
+// create virtual table t using notes();
+// A writeable table with transactions and an overloaded shout() function,
+// with every stored note prefixed by the module's aux.
+#[repr(C)]
+pub struct NotesTable {
+    base: sqlite3_vtab,
+    prefix: String,
+    notes: Vec<String>,
+}
+
+impl<'vtab> VTab<'vtab> for NotesTable {
+    type Aux = String;
+    type Cursor = NotesCursor;
+
+    fn connect(
+        _db: *mut sqlite3,
+        aux: Option<&Self::Aux>,
+        _args: VTabArguments,
+    ) -> Result<(String, NotesTable)> {
+        let base: sqlite3_vtab = unsafe { mem::zeroed() };
+        let vtab = NotesTable {
+            base,
+            prefix: aux.cloned().unwrap_or_default(),
+            notes: vec![],
+        };
+        Ok(("CREATE TABLE x(note)".to_owned(), vtab))
+    }
+
+    fn best_index(&mut self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        info.set_estimated_cost(100.0);
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<NotesCursor> {
+        let base: sqlite3_vtab_cursor = unsafe { mem::zeroed() };
+        Ok(NotesCursor {
+            base,
+            notes: self.notes.clone(),
+            rowid: 0,
+        })
+    }
+}
+
+impl<'vtab> VTabWriteable<'vtab> for NotesTable {
+    fn update(&mut self, operation: UpdateOperation, p_rowid: *mut i64) -> Result<()> {
+        log_event("update");
+        if let UpdateOperation::Insert { values, .. } = operation {
+            let note = values[0].get::<String>()?;
+            self.notes.push(format!("{}{}", self.prefix, note));
+            unsafe { *p_rowid = self.notes.len() as i64 };
+        }
+        Ok(())
+    }
+}
+
+impl<'vtab> VTabWriteableWithTransactions<'vtab> for NotesTable {
+    fn begin(&mut self) -> Result<()> {
+        log_event("begin");
+        Ok(())
+    }
+    fn sync(&mut self) -> Result<()> {
+        log_event("sync");
+        Ok(())
+    }
+    fn commit(&mut self) -> Result<()> {
+        log_event("commit");
+        Ok(())
+    }
+    fn rollback(&mut self) -> Result<()> {
+        log_event("rollback");
+        Ok(())
+    }
+}
+
+impl<'vtab> VTabFind<'vtab> for NotesTable {
+    fn find_function(&mut self, _argc: i32, name: &str) -> Option<FindResult> {
+        if name == "shout" {
+            let shout = |context: *mut sqlite3_context, values: &[*mut sqlite3_value]| {
+                api::result_text(context, api::value_text(&values[0])?.to_uppercase())
+            };
+            return Some((scalar_function_raw(shout), None, None));
+        }
+        None
+    }
+}
+
+#[repr(C)]
+pub struct NotesCursor {
+    base: sqlite3_vtab_cursor,
+    notes: Vec<String>,
+    rowid: usize,
+}
+
+impl VTabCursor for NotesCursor {
+    fn filter(
+        &mut self,
+        _idx_num: c_int,
+        _idx_str: Option<&str>,
+        _values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        self.rowid = 0;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.rowid += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.rowid >= self.notes.len()
+    }
+
+    fn column(&self, context: *mut sqlite3_context, _i: c_int) -> Result<()> {
+        api::result_text(context, &self.notes[self.rowid])
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.rowid as i64 + 1)
+    }
+}
+
+// overloaded by the notes table, so only called on other arguments
+fn shout(context: *mut sqlite3_context, _values: &[*mut sqlite3_value]) -> Result<()> {
+    api::result_text(context, "not overloaded")
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_modulebuilder_init(db: *mut sqlite3) -> Result<()> {
+    define_scalar_function(db, "shout", 1, shout, FunctionFlags::UTF8)?;
+    ModuleBuilder::<NotesTable>::new("notes")
+        .aux("note: ".to_owned())
+        .writeable()
+        .transactions()
+        .find_function()
+        .register(db)?;
+    ModuleBuilder::<NotesTable>::new("notes_fn")
+        .eponymous_only()
+        .find_function()
+        .register(db)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(std::mem::transmute(
+                sqlite3_modulebuilder_init as *const (),
+            )));
+        }
+
+        let db = Connection::open_in_memory().unwrap();
+
+        db.execute("create virtual table t using notes()", [])
+            .unwrap();
+        // the CREATE's own transaction syncs and commits the new table
+        assert_eq!(*EVENTS.lock().unwrap(), ["sync", "commit"]);
+        EVENTS.lock().unwrap().clear();
+
+        db.execute("insert into t values ('a'), ('b')", []).unwrap();
+        assert_eq!(
+            *EVENTS.lock().unwrap(),
+            ["begin", "update", "update", "sync", "commit"]
+        );
+
+        let notes: Vec<String> = db
+            .prepare("select shout(note) from t")
+            .unwrap()
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(notes, ["NOTE: A", "NOTE: B"]);
+
+        assert_eq!(
+            db.query_row("select shout('a')", [], |r| r.get::<_, String>(0))
+                .unwrap(),
+            "not overloaded"
+        );
+
+        // eponymous-only modules can't be used in CREATE VIRTUAL TABLE
+        let err = db
+            .execute("create virtual table t2 using notes_fn()", [])
+            .unwrap_err();
+        assert_eq!(err.to_string(), "no such module: notes_fn");
+        assert_eq!(
+            db.query_row("select count(*) from notes_fn", [], |r| r.get::<_, i64>(0))
+                .unwrap(),
+            0
+        );
+
+        // and without writeable(), inserts aren't supported
+        let err = db
+            .execute("insert into notes_fn values ('c')", [])
+            .unwrap_err();
+        assert_eq!(err.to_string(), "table notes_fn may not be modified");
+    }
+}
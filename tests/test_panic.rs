@@ -0,0 +1,175 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api, define_collation, define_scalar_function, define_table_function,
+    table::{BestIndexError, IndexInfo, VTab, VTabArguments, VTabCursor},
+    Result,
+};
+
+use std::{mem, os::raw::c_int};
+
+// panics_on(value) - panics when value is negative
+fn panics_on(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    let value = api::value_int64(&values[0]);
+    if value < 0 {
+        panic!("negative value: {}", value);
+    }
+    api::result_int64(context, value);
+    Ok(())
+}
+
+// select value from panic_at(3) - yields 1, 2, then panics on the 3rd column read
+#[repr(C)]
+pub struct PanicTable {
+    base: sqlite3_vtab,
+}
+
+impl<'vtab> VTab<'vtab> for PanicTable {
+    type Aux = ();
+    type Cursor = PanicCursor;
+
+    fn connect(
+        _db: *mut sqlite3,
+        _aux: Option<&Self::Aux>,
+        _args: VTabArguments,
+    ) -> Result<(String, PanicTable)> {
+        let base: sqlite3_vtab = unsafe { mem::zeroed() };
+        Ok((
+            "CREATE TABLE x(value, at hidden)".to_owned(),
+            PanicTable { base },
+        ))
+    }
+
+    fn destroy(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn best_index(&mut self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        for mut constraint in info.constraints() {
+            if constraint.column_idx() == 1 && constraint.usable() {
+                constraint.set_omit(true);
+                constraint.set_argv_index(1);
+            }
+        }
+        info.set_idxnum(1);
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<PanicCursor> {
+        let base: sqlite3_vtab_cursor = unsafe { mem::zeroed() };
+        Ok(PanicCursor {
+            base,
+            value: 1,
+            at: 0,
+        })
+    }
+}
+
+#[repr(C)]
+pub struct PanicCursor {
+    base: sqlite3_vtab_cursor,
+    value: i64,
+    at: i64,
+}
+
+impl VTabCursor for PanicCursor {
+    fn filter(
+        &mut self,
+        _idx_num: c_int,
+        _idx_str: Option<&str>,
+        values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        self.at = api::value_int64(&values[0]);
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.value += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.value > 10
+    }
+
+    fn column(&self, context: *mut sqlite3_context, _i: c_int) -> Result<()> {
+        if self.value == self.at {
+            panic!("column panicked at {}", self.value);
+        }
+        api::result_int64(context, self.value);
+        Ok(())
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.value)
+    }
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_panic_init(db: *mut sqlite3) -> Result<()> {
+    define_scalar_function(db, "panics_on", 1, panics_on, FunctionFlags::UTF8)?;
+    define_collation(db, "panicky", |a, b| {
+        if a == b"boom" || b == b"boom" {
+            panic!("collation panicked");
+        }
+        a.cmp(b) as i32
+    })?;
+    define_table_function::<PanicTable>(db, "panic_at", None)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection, ErrorCode};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(std::mem::transmute(sqlite3_panic_init as *const ())));
+        }
+        // keep the test output clean, the panics below are expected
+        std::panic::set_hook(Box::new(|_| {}));
+
+        let db = Connection::open_in_memory().unwrap();
+
+        let err = db
+            .query_row("select panics_on(-1)", [], |r| r.get::<_, i64>(0))
+            .unwrap_err();
+        assert_eq!(
+            err.sqlite_error_code(),
+            Some(ErrorCode::InternalMalfunction)
+        );
+        assert_eq!(err.to_string(), "negative value: -1");
+
+        // the connection is still usable afterwards
+        let result: i64 = db
+            .query_row("select panics_on(1)", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(result, 1);
+
+        let err = db
+            .prepare("select value from panic_at(3)")
+            .unwrap()
+            .query_map([], |r| r.get::<_, i64>(0))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>, _>>()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "column panicked at 3");
+
+        let result: Vec<i64> = db
+            .prepare("select value from panic_at(20)")
+            .unwrap()
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(result, [1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
+
+        // collations can't report errors, a panic compares as equal
+        let result: bool = db
+            .query_row("select 'boom' = 'other' collate panicky", [], |r| r.get(0))
+            .unwrap();
+        assert!(result);
+    }
+}
@@ -0,0 +1,193 @@
+use serde::{Deserialize, Serialize};
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api, define_table_function,
+    table::{BestIndexError, ConstraintOperator, IndexInfo, Plan, VTab, VTabArguments, VTabCursor},
+    Result,
+};
+
+use std::{mem, os::raw::c_int};
+
+/// How a scan of the digits table is bounded
+#[derive(Debug, Serialize, Deserialize)]
+struct Bounds {
+    lower: bool,
+    upper: bool,
+}
+
+// select value from digits where value >= 3 and value < 7;
+// The digits 0 to 9, where >= and < constraints are passed to filter by name,
+// in whatever order SQLite lists them.
+#[repr(C)]
+pub struct DigitsTable {
+    base: sqlite3_vtab,
+}
+
+impl<'vtab> VTab<'vtab> for DigitsTable {
+    type Aux = ();
+    type Cursor = DigitsCursor;
+
+    fn connect(
+        _db: *mut sqlite3,
+        _aux: Option<&Self::Aux>,
+        _args: VTabArguments,
+    ) -> Result<(String, DigitsTable)> {
+        let base: sqlite3_vtab = unsafe { mem::zeroed() };
+        Ok(("CREATE TABLE x(value)".to_owned(), DigitsTable { base }))
+    }
+
+    fn best_index(&mut self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        let mut plan = Plan::new(Bounds {
+            lower: false,
+            upper: false,
+        });
+        for mut constraint in info.constraints() {
+            if !constraint.usable() {
+                continue;
+            }
+            match constraint.op() {
+                Some(ConstraintOperator::GE) if !plan.plan.lower => {
+                    plan.argument(&mut constraint, "lower");
+                    constraint.set_omit(true);
+                    plan.plan.lower = true;
+                }
+                Some(ConstraintOperator::LT) if !plan.plan.upper => {
+                    plan.argument(&mut constraint, "upper");
+                    constraint.set_omit(true);
+                    plan.plan.upper = true;
+                }
+                _ => (),
+            }
+        }
+        info.set_plan(&plan).map_err(|_| BestIndexError::Error)?;
+        info.set_estimated_cost(10.0);
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<DigitsCursor> {
+        let base: sqlite3_vtab_cursor = unsafe { mem::zeroed() };
+        Ok(DigitsCursor {
+            base,
+            value: 0,
+            stop: 0,
+        })
+    }
+}
+
+#[repr(C)]
+pub struct DigitsCursor {
+    base: sqlite3_vtab_cursor,
+    value: i64,
+    stop: i64,
+}
+
+impl VTabCursor for DigitsCursor {
+    fn filter(
+        &mut self,
+        _idx_num: c_int,
+        idx_str: Option<&str>,
+        values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        let (bounds, arguments) = Plan::<Bounds>::decode(idx_str, values)?;
+        let lower: Option<i64> = arguments.get("lower").map(|v| v.get()).transpose()?;
+        let upper: Option<i64> = arguments.get("upper").map(|v| v.get()).transpose()?;
+        assert_eq!(bounds.lower, lower.is_some());
+        assert_eq!(bounds.upper, upper.is_some());
+        self.value = lower.unwrap_or(0).max(0);
+        self.stop = upper.unwrap_or(10).min(10);
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.value += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.value >= self.stop
+    }
+
+    fn column(&self, context: *mut sqlite3_context, _i: c_int) -> Result<()> {
+        api::result_int64(context, self.value);
+        Ok(())
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.value)
+    }
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_queryplan_init(db: *mut sqlite3) -> Result<()> {
+    define_table_function::<DigitsTable>(db, "digits", None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    fn values(db: &Connection, sql: &str) -> Vec<i64> {
+        db.prepare(sql)
+            .unwrap()
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(std::mem::transmute(
+                sqlite3_queryplan_init as *const (),
+            )));
+        }
+
+        let db = Connection::open_in_memory().unwrap();
+        assert_eq!(values(&db, "select value from digits").len(), 10);
+        assert_eq!(
+            values(
+                &db,
+                "select value from digits where value >= 3 and value < 7"
+            ),
+            [3, 4, 5, 6]
+        );
+        assert_eq!(
+            values(
+                &db,
+                "select value from digits where value < 7 and value >= 3"
+            ),
+            [3, 4, 5, 6]
+        );
+        assert_eq!(
+            values(&db, "select value from digits where value < 2"),
+            [0, 1]
+        );
+        // only the first >= is passed along, SQLite checks the other one
+        assert_eq!(
+            values(
+                &db,
+                "select value from digits where value >= 8 and value >= 5"
+            ),
+            [8, 9]
+        );
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let err = Plan::<Bounds>::decode(None, &[]).unwrap_err();
+        assert_eq!(err.result_error_message(), "missing query plan");
+
+        let err = Plan::<Bounds>::decode(
+            Some(r#"{"plan":{"lower":true,"upper":false},"arguments":["lower"]}"#),
+            &[],
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.result_error_message(),
+            "query plan expected 1 arguments, got 0"
+        );
+    }
+}
@@ -0,0 +1,234 @@
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api,
+    table::{
+        BestIndexError, IndexInfo, UpdateOperation, VTab, VTabArguments, VTabCursor, VTabWriteable,
+        VTabWriteableNestedTransactions, VTabWriteableWithTransactions,
+    },
+    ModuleBuilder, Result,
+};
+
+use std::{mem, os::raw::c_int};
+
+// create virtual table t using items();
+// An in-memory table that snapshots its rows at the start of every
+// transaction and savepoint, to undo writes on ROLLBACK and ROLLBACK TO.
+#[repr(C)]
+pub struct ItemsTable {
+    base: sqlite3_vtab,
+    items: Vec<String>,
+    /// rows at the start of the current transaction
+    transaction: Option<Vec<String>>,
+    /// (savepoint id, rows at the start of the savepoint), ordered by id
+    savepoints: Vec<(c_int, Vec<String>)>,
+}
+
+impl<'vtab> VTab<'vtab> for ItemsTable {
+    type Aux = ();
+    type Cursor = ItemsCursor;
+
+    fn connect(
+        _db: *mut sqlite3,
+        _aux: Option<&Self::Aux>,
+        _args: VTabArguments,
+    ) -> Result<(String, ItemsTable)> {
+        let base: sqlite3_vtab = unsafe { mem::zeroed() };
+        let vtab = ItemsTable {
+            base,
+            items: vec![],
+            transaction: None,
+            savepoints: vec![],
+        };
+        Ok(("CREATE TABLE x(item)".to_owned(), vtab))
+    }
+
+    fn best_index(&mut self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        info.set_estimated_cost(100.0);
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<ItemsCursor> {
+        let base: sqlite3_vtab_cursor = unsafe { mem::zeroed() };
+        Ok(ItemsCursor {
+            base,
+            items: self.items.clone(),
+            rowid: 0,
+        })
+    }
+}
+
+impl<'vtab> VTabWriteable<'vtab> for ItemsTable {
+    fn update(&mut self, operation: UpdateOperation, p_rowid: *mut i64) -> Result<()> {
+        match operation {
+            UpdateOperation::Insert { values, .. } => {
+                self.items.push(values[0].get::<String>()?);
+                unsafe { *p_rowid = self.items.len() as i64 };
+                Ok(())
+            }
+            _ => Err("only inserts are supported".into()),
+        }
+    }
+}
+
+impl<'vtab> VTabWriteableWithTransactions<'vtab> for ItemsTable {
+    fn begin(&mut self) -> Result<()> {
+        self.transaction = Some(self.items.clone());
+        Ok(())
+    }
+    fn sync(&mut self) -> Result<()> {
+        Ok(())
+    }
+    fn commit(&mut self) -> Result<()> {
+        self.transaction = None;
+        self.savepoints.clear();
+        Ok(())
+    }
+    fn rollback(&mut self) -> Result<()> {
+        if let Some(items) = self.transaction.take() {
+            self.items = items;
+        }
+        self.savepoints.clear();
+        Ok(())
+    }
+}
+
+impl<'vtab> VTabWriteableNestedTransactions<'vtab> for ItemsTable {
+    fn savepoint(&mut self, id: c_int) -> Result<()> {
+        self.savepoints.retain(|(savepoint, _)| *savepoint < id);
+        self.savepoints.push((id, self.items.clone()));
+        Ok(())
+    }
+    fn release(&mut self, id: c_int) -> Result<()> {
+        self.savepoints.retain(|(savepoint, _)| *savepoint < id);
+        Ok(())
+    }
+    fn rollback_to(&mut self, id: c_int) -> Result<()> {
+        self.savepoints.retain(|(savepoint, _)| *savepoint <= id);
+        match self.savepoints.last() {
+            Some((savepoint, items)) if *savepoint == id => {
+                self.items = items.clone();
+                Ok(())
+            }
+            _ => Err(format!("unknown savepoint {}", id).into()),
+        }
+    }
+}
+
+#[repr(C)]
+pub struct ItemsCursor {
+    base: sqlite3_vtab_cursor,
+    items: Vec<String>,
+    rowid: usize,
+}
+
+impl VTabCursor for ItemsCursor {
+    fn filter(
+        &mut self,
+        _idx_num: c_int,
+        _idx_str: Option<&str>,
+        _values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        self.rowid = 0;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.rowid += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.rowid >= self.items.len()
+    }
+
+    fn column(&self, context: *mut sqlite3_context, _i: c_int) -> Result<()> {
+        api::result_text(context, &self.items[self.rowid])
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.rowid as i64 + 1)
+    }
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_savepoints_init(db: *mut sqlite3) -> Result<()> {
+    ModuleBuilder::<ItemsTable>::new("items")
+        .writeable()
+        .nested_transactions()
+        .register(db)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    fn items(db: &Connection) -> Vec<String> {
+        db.prepare("select item from t")
+            .unwrap()
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(std::mem::transmute(
+                sqlite3_savepoints_init as *const (),
+            )));
+        }
+
+        let db = Connection::open_in_memory().unwrap();
+        db.execute("create virtual table t using items()", [])
+            .unwrap();
+
+        db.execute_batch(
+            "
+            begin;
+            insert into t values ('a');
+            savepoint s1;
+            insert into t values ('b');
+            savepoint s2;
+            insert into t values ('c');
+            ",
+        )
+        .unwrap();
+        assert_eq!(items(&db), ["a", "b", "c"]);
+
+        db.execute("rollback to s2", []).unwrap();
+        assert_eq!(items(&db), ["a", "b"]);
+
+        // s2 is still active after a ROLLBACK TO
+        db.execute("insert into t values ('d')", []).unwrap();
+        db.execute("rollback to s2", []).unwrap();
+        assert_eq!(items(&db), ["a", "b"]);
+
+        db.execute("insert into t values ('e')", []).unwrap();
+        db.execute("release s2", []).unwrap();
+        assert_eq!(items(&db), ["a", "b", "e"]);
+
+        // rolling back an outer savepoint undoes everything in the released inner one
+        db.execute("rollback to s1", []).unwrap();
+        assert_eq!(items(&db), ["a"]);
+
+        db.execute_batch("release s1; commit;").unwrap();
+        assert_eq!(items(&db), ["a"]);
+
+        // and a full ROLLBACK still undoes the whole transaction
+        db.execute_batch(
+            "
+            begin;
+            insert into t values ('f');
+            savepoint s3;
+            insert into t values ('g');
+            rollback;
+            ",
+        )
+        .unwrap();
+        assert_eq!(items(&db), ["a"]);
+    }
+}
@@ -47,11 +47,11 @@ impl<'vtab> VTab<'vtab> for GenerateSeriesTable {
         // TODO db.config(VTabConfig::Innocuous)?;
         Ok((CREATE_SQL.to_owned(), vtab))
     }
-    fn destroy(&self) -> Result<()> {
+    fn destroy(&mut self) -> Result<()> {
         Ok(())
     }
 
-    fn best_index(&self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+    fn best_index(&mut self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
         let mut has_start = false;
         let mut has_stop = false;
         for mut constraint in info.constraints() {
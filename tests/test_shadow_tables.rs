@@ -0,0 +1,262 @@
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api,
+    table::{
+        BestIndexError, IndexInfo, ShadowTables, UpdateOperation, VTab, VTabArguments, VTabCursor,
+        VTabRename, VTabShadowNames, VTabWriteable,
+    },
+    Error, ModuleBuilder, Result,
+};
+
+use std::{mem, os::raw::c_int};
+
+const SHADOW_NAMES: &[&str] = &["config", "data"];
+
+// create virtual table t using vectors(dimensions);
+// Stores vectors (as JSON text) in a "t_data" shadow table, and its number of
+// dimensions in a "t_config" shadow table.
+#[repr(C)]
+pub struct VectorsTable {
+    base: sqlite3_vtab,
+    shadow: ShadowTables,
+    dimensions: i64,
+}
+
+impl<'vtab> VTab<'vtab> for VectorsTable {
+    type Aux = ();
+    type Cursor = VectorsCursor;
+
+    fn create(
+        db: *mut sqlite3,
+        _aux: Option<&Self::Aux>,
+        args: VTabArguments,
+    ) -> Result<(String, VectorsTable)> {
+        let dimensions: i64 = args
+            .arguments
+            .first()
+            .and_then(|arg| arg.parse().ok())
+            .ok_or_else(|| Error::new_message("vectors() requires a number of dimensions"))?;
+        let shadow = ShadowTables::new(db, &args);
+        shadow.create_table("config", "key text primary key, value")?;
+        shadow.create_table("data", "id integer primary key, vector text")?;
+        shadow.exec(&format!(
+            "insert into {} values ('dimensions', {})",
+            shadow.qualified_name("config"),
+            dimensions
+        ))?;
+        Self::connect(db, None, args)
+    }
+
+    fn connect(
+        db: *mut sqlite3,
+        _aux: Option<&Self::Aux>,
+        args: VTabArguments,
+    ) -> Result<(String, VectorsTable)> {
+        let shadow = ShadowTables::new(db, &args);
+        let dimensions = shadow
+            .query(
+                &format!(
+                    "select value from {} where key = 'dimensions'",
+                    shadow.qualified_name("config")
+                ),
+                |row| row[0].get::<i64>(),
+            )?
+            .pop()
+            .ok_or_else(|| Error::new_message("missing dimensions"))?;
+        let base: sqlite3_vtab = unsafe { mem::zeroed() };
+        let vtab = VectorsTable {
+            base,
+            shadow,
+            dimensions,
+        };
+        Ok(("CREATE TABLE x(vector)".to_owned(), vtab))
+    }
+
+    fn destroy(&mut self) -> Result<()> {
+        for suffix in SHADOW_NAMES {
+            self.shadow.drop_table(suffix)?;
+        }
+        Ok(())
+    }
+
+    fn best_index(&mut self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        info.set_estimated_cost(100.0);
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<VectorsCursor> {
+        let rows = self.shadow.query(
+            &format!(
+                "select id, vector from {} order by id",
+                self.shadow.qualified_name("data")
+            ),
+            |row| Ok((row[0].get::<i64>()?, row[1].get::<String>()?)),
+        )?;
+        let base: sqlite3_vtab_cursor = unsafe { mem::zeroed() };
+        Ok(VectorsCursor { base, rows, idx: 0 })
+    }
+}
+
+impl<'vtab> VTabWriteable<'vtab> for VectorsTable {
+    fn update(&mut self, operation: UpdateOperation, p_rowid: *mut i64) -> Result<()> {
+        let values = match operation {
+            UpdateOperation::Insert { values, .. } => values,
+            _ => return Err(Error::new_message("only inserts are supported")),
+        };
+        let vector: String = values[0].get()?;
+        let dimensions = vector.split(',').count() as i64;
+        if dimensions != self.dimensions {
+            return Err(Error::new_message(format!(
+                "expected {} dimensions, got {}",
+                self.dimensions, dimensions
+            )));
+        }
+        let rowid = self
+            .shadow
+            .query(
+                &format!(
+                    "insert into {}(vector) values ('[{}]') returning id",
+                    self.shadow.qualified_name("data"),
+                    vector.replace('\'', "''")
+                ),
+                |row| row[0].get::<i64>(),
+            )?
+            .pop()
+            .ok_or_else(|| Error::new_message("insert returned no rowid"))?;
+        unsafe { *p_rowid = rowid };
+        Ok(())
+    }
+}
+
+impl<'vtab> VTabRename<'vtab> for VectorsTable {
+    fn rename(&mut self, new_name: &str) -> Result<()> {
+        self.shadow.rename_tables(SHADOW_NAMES, new_name)
+    }
+}
+
+impl<'vtab> VTabShadowNames<'vtab> for VectorsTable {
+    fn shadow_names() -> &'static [&'static str] {
+        SHADOW_NAMES
+    }
+}
+
+#[repr(C)]
+pub struct VectorsCursor {
+    base: sqlite3_vtab_cursor,
+    rows: Vec<(i64, String)>,
+    idx: usize,
+}
+
+impl VTabCursor for VectorsCursor {
+    fn filter(
+        &mut self,
+        _idx_num: c_int,
+        _idx_str: Option<&str>,
+        _values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        self.idx = 0;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.idx += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.idx >= self.rows.len()
+    }
+
+    fn column(&self, context: *mut sqlite3_context, _i: c_int) -> Result<()> {
+        api::result_text(context, &self.rows[self.idx].1)
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.rows[self.idx].0)
+    }
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_shadowtables_init(db: *mut sqlite3) -> Result<()> {
+    ModuleBuilder::<VectorsTable>::new("vectors")
+        .writeable()
+        .renameable()
+        .shadow_names()
+        .register(db)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{config::DbConfig, ffi::sqlite3_auto_extension, Connection};
+
+    fn tables(db: &Connection) -> Vec<String> {
+        db.prepare("select name from sqlite_master where type = 'table' order by name")
+            .unwrap()
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>, _>>()
+            .unwrap()
+    }
+
+    fn vectors(db: &Connection, table: &str) -> Vec<String> {
+        db.prepare(&format!("select vector from {}", table))
+            .unwrap()
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(std::mem::transmute(
+                sqlite3_shadowtables_init as *const (),
+            )));
+        }
+
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "
+            create virtual table t using vectors(2);
+            insert into t values ('1,2'), ('3,4');
+            ",
+        )
+        .unwrap();
+        assert_eq!(tables(&db), ["t", "t_config", "t_data"]);
+        assert_eq!(vectors(&db, "t"), ["[1,2]", "[3,4]"]);
+        assert_eq!(
+            db.execute("insert into t values ('1,2,3')", [])
+                .unwrap_err()
+                .to_string(),
+            "expected 2 dimensions, got 3"
+        );
+
+        // shadow tables are still writeable without defensive mode
+        db.execute("insert into t_data(vector) values ('[5,6]')", [])
+            .unwrap();
+        assert_eq!(vectors(&db, "t"), ["[1,2]", "[3,4]", "[5,6]"]);
+
+        // but with it, only the virtual table can change them
+        db.set_db_config(DbConfig::SQLITE_DBCONFIG_DEFENSIVE, true)
+            .unwrap();
+        assert_eq!(
+            db.execute("delete from t_data", [])
+                .unwrap_err()
+                .to_string(),
+            "table t_data may not be modified"
+        );
+        db.execute("insert into t values ('7,8')", []).unwrap();
+        assert_eq!(vectors(&db, "t_data"), ["[1,2]", "[3,4]", "[5,6]", "[7,8]"]);
+
+        db.execute("alter table t rename to u", []).unwrap();
+        assert_eq!(tables(&db), ["u", "u_config", "u_data"]);
+        assert_eq!(vectors(&db, "u").len(), 4);
+
+        db.execute("drop table u", []).unwrap();
+        assert!(tables(&db).is_empty());
+    }
+}
@@ -0,0 +1,171 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api, define_virtual_table,
+    table::{BestIndexError, IndexInfo, VTab, VTabArguments, VTabCursor},
+    Result,
+};
+
+use std::{
+    mem,
+    os::raw::c_int,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+/// Number of Words values that were dropped
+static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+/// The words of a table, shared between the table and all its cursors
+struct Words(Vec<String>);
+
+impl Drop for Words {
+    fn drop(&mut self) {
+        DROPPED.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+// create virtual table t using words(alpha, beta, gamma);
+// One row per argument, where cursors read the words through an Arc instead
+// of borrowing the table.
+#[repr(C)]
+pub struct WordsTable {
+    base: sqlite3_vtab,
+    words: Arc<Words>,
+}
+
+impl<'vtab> VTab<'vtab> for WordsTable {
+    type Aux = ();
+    type Cursor = WordsCursor;
+
+    fn connect(
+        _db: *mut sqlite3,
+        _aux: Option<&Self::Aux>,
+        args: VTabArguments,
+    ) -> Result<(String, WordsTable)> {
+        let base: sqlite3_vtab = unsafe { mem::zeroed() };
+        let words = Words(args.arguments.iter().map(|s| s.trim().to_owned()).collect());
+        Ok((
+            "CREATE TABLE x(word)".to_owned(),
+            WordsTable {
+                base,
+                words: Arc::new(words),
+            },
+        ))
+    }
+
+    fn best_index(&mut self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        info.set_estimated_cost(self.words.0.len() as f64);
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<WordsCursor> {
+        let base: sqlite3_vtab_cursor = unsafe { mem::zeroed() };
+        Ok(WordsCursor {
+            base,
+            words: Arc::clone(&self.words),
+            idx: 0,
+        })
+    }
+}
+
+#[repr(C)]
+pub struct WordsCursor {
+    base: sqlite3_vtab_cursor,
+    words: Arc<Words>,
+    idx: usize,
+}
+
+impl VTabCursor for WordsCursor {
+    fn filter(
+        &mut self,
+        _idx_num: c_int,
+        _idx_str: Option<&str>,
+        _values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        self.idx = 0;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.idx += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.idx >= self.words.0.len()
+    }
+
+    fn column(&self, context: *mut sqlite3_context, _i: c_int) -> Result<()> {
+        api::result_text(context, &self.words.0[self.idx])?;
+        Ok(())
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.idx as i64)
+    }
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_sharedstate_init(db: *mut sqlite3) -> Result<()> {
+    define_virtual_table::<WordsTable>(db, "words", None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    fn dropped() -> usize {
+        DROPPED.swap(0, Ordering::SeqCst)
+    }
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(std::mem::transmute(
+                sqlite3_sharedstate_init as *const (),
+            )));
+        }
+
+        let db = Connection::open_in_memory().unwrap();
+        db.execute("create virtual table t using words(alpha, beta, gamma)", [])
+            .unwrap();
+
+        // a self-join has two cursors open on the same table at once
+        let pairs: Vec<(String, String)> = db
+            .prepare("select a.word, b.word from t a join t b on a.word < b.word order by 1, 2")
+            .unwrap()
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(
+            pairs,
+            [("alpha", "beta"), ("alpha", "gamma"), ("beta", "gamma")]
+                .map(|(a, b)| (a.to_owned(), b.to_owned()))
+        );
+        let count: i64 = db
+            .query_row("select count(*) from t a, t b, t c", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(count, 27);
+        // closing the cursors doesn't free the table's words
+        assert_eq!(dropped(), 0);
+
+        // DROP TABLE frees the table exactly once
+        db.execute("drop table t", []).unwrap();
+        assert_eq!(dropped(), 1);
+
+        // and so does closing the connection
+        db.execute("create virtual table u using words(delta)", [])
+            .unwrap();
+        let word: String = db
+            .query_row("select word from u", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(word, "delta");
+        drop(db);
+        assert_eq!(dropped(), 1);
+    }
+}
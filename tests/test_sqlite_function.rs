@@ -0,0 +1,119 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::Result;
+
+#[sqlite_functions]
+mod functions {
+    use sqlite_loadable::prelude::*;
+    use sqlite_loadable::{Error, Result};
+
+    #[sqlite_function(name = "xyz_add", deterministic, innocuous)]
+    fn add(a: i64, b: i64) -> Result<i64> {
+        Ok(a + b)
+    }
+
+    #[sqlite_function(deterministic)]
+    pub fn xyz_shout(text: &str) -> Result<String> {
+        Ok(format!("{}!", text.to_uppercase()))
+    }
+
+    #[sqlite_function(name = "xyz_checked_div")]
+    fn checked_div(a: i64, b: i64) -> Result<Option<i64>> {
+        if b == 0 {
+            return Err(Error::new_message("division by zero"));
+        }
+        Ok(a.checked_div(b))
+    }
+
+    // not exported to SQL
+    #[allow(dead_code)]
+    fn helper() {}
+
+    // functions in nested modules are registered too
+    mod text {
+        use sqlite_loadable::Result;
+
+        #[sqlite_function(deterministic)]
+        fn xyz_reverse(text: &str) -> Result<String> {
+            Ok(text.chars().rev().collect())
+        }
+    }
+
+    // and so are nested modules with their own register_functions()
+    #[sqlite_functions]
+    pub mod math {
+        use sqlite_loadable::Result;
+
+        #[sqlite_function]
+        fn xyz_square(x: i64) -> Result<i64> {
+            Ok(x * x)
+        }
+    }
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_sqlitefunction_init(db: *mut sqlite3) -> Result<()> {
+    functions::register_functions(db)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(std::mem::transmute(
+                sqlite3_sqlitefunction_init as *const (),
+            )));
+        }
+
+        let db = Connection::open_in_memory().unwrap();
+
+        let result: i64 = db
+            .query_row("select xyz_add(40, 2)", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(result, 42);
+
+        let result: String = db
+            .query_row("select xyz_shout('hey')", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(result, "HEY!");
+
+        let result: i64 = db
+            .query_row("select xyz_checked_div(9, 3)", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(result, 3);
+
+        let err = db
+            .query_row("select xyz_checked_div(9, 0)", [], |r| r.get::<_, i64>(0))
+            .unwrap_err();
+        assert_eq!(err.to_string(), "division by zero");
+
+        let err = db
+            .query_row("select xyz_add(1, 'a')", [], |r| r.get::<_, i64>(0))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "xyz_add(): argument 2 expected integer, got text"
+        );
+
+        let result: (String, i64) = db
+            .query_row("select xyz_reverse('abc'), xyz_square(7)", [], |r| {
+                Ok((r.get(0)?, r.get(1)?))
+            })
+            .unwrap();
+        assert_eq!(result, ("cba".to_owned(), 49));
+
+        // flags are applied to the registered function
+        let flags: (bool, bool) = db
+            .query_row(
+                "select flags & 0x800 != 0, flags & 0x200000 != 0 from pragma_function_list where name = 'xyz_add'",
+                [],
+                |r| Ok((r.get(0)?, r.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(flags, (true, true));
+    }
+}
@@ -0,0 +1,319 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api, define_scalar_function,
+    table::{
+        BestIndexError, IndexInfo, UpdateOperation, VTab, VTabArguments, VTabTypedCursor,
+        VTabWriteable,
+    },
+    types::SqlPointer,
+    ModuleBuilder, Result, SqlValue,
+};
+
+use serde_json::json;
+use std::{
+    mem,
+    os::raw::c_int,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Number of times the "content" column was read
+static CONTENT_READS: AtomicUsize = AtomicUsize::new(0);
+
+const HANDLE_POINTER: &[u8] = b"file_handle\0";
+
+/// A pretend open file, passed to handle_name() as a pointer
+struct Handle(String);
+
+// create virtual table t using files();
+// insert into t(name, content) values ('a.txt', 'hello');
+// A (name, content) table, where every other column is computed from them.
+#[repr(C)]
+pub struct FilesTable {
+    base: sqlite3_vtab,
+    files: Vec<(String, Vec<u8>)>,
+}
+
+impl<'vtab> VTab<'vtab> for FilesTable {
+    type Aux = ();
+    type Cursor = FilesCursor;
+
+    fn connect(
+        _db: *mut sqlite3,
+        _aux: Option<&Self::Aux>,
+        _args: VTabArguments,
+    ) -> Result<(String, FilesTable)> {
+        let base: sqlite3_vtab = unsafe { mem::zeroed() };
+        Ok((
+            "CREATE TABLE x(name, content, size, ratio, stats, label, handle, reversed, kind, magic)"
+                .to_owned(),
+            FilesTable {
+                base,
+                files: vec![],
+            },
+        ))
+    }
+
+    fn best_index(&mut self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        info.set_estimated_cost(100.0);
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<FilesCursor> {
+        let base: sqlite3_vtab_cursor = unsafe { mem::zeroed() };
+        Ok(FilesCursor {
+            base,
+            files: self.files.clone(),
+            idx: 0,
+        })
+    }
+}
+
+impl<'vtab> VTabWriteable<'vtab> for FilesTable {
+    fn update(&mut self, operation: UpdateOperation, _p_rowid: *mut i64) -> Result<()> {
+        match operation {
+            UpdateOperation::Insert { values, .. } => {
+                self.files
+                    .push((values[0].get()?, values[1].get::<&[u8]>()?.to_vec()));
+            }
+            UpdateOperation::Update {
+                old_rowid, values, ..
+            } => {
+                let file = &mut self.files[old_rowid.get::<i64>()? as usize];
+                if let Some(name) = &values[0] {
+                    file.0 = name.get()?;
+                }
+                if let Some(content) = &values[1] {
+                    file.1 = content.get::<&[u8]>()?.to_vec();
+                }
+            }
+            UpdateOperation::Delete { .. } => return Err("files can't be deleted".into()),
+        }
+        Ok(())
+    }
+}
+
+#[repr(C)]
+pub struct FilesCursor {
+    base: sqlite3_vtab_cursor,
+    files: Vec<(String, Vec<u8>)>,
+    idx: usize,
+}
+
+impl VTabTypedCursor for FilesCursor {
+    fn filter(
+        &mut self,
+        _idx_num: c_int,
+        _idx_str: Option<&str>,
+        _values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        self.idx = 0;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.idx += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.idx >= self.files.len()
+    }
+
+    fn column(&self, i: c_int) -> Result<SqlValue<'_>> {
+        let (name, content) = &self.files[self.idx];
+        Ok(match i {
+            0 => name.as_str().into(),
+            1 => {
+                CONTENT_READS.fetch_add(1, Ordering::SeqCst);
+                content.as_slice().into()
+            }
+            2 => (content.len() as i64).into(),
+            3 => (content.len() as f64 / name.len() as f64).into(),
+            4 => json!({ "name": name, "size": content.len() }).into(),
+            5 => format!("{}\0{}", name, content.len()).into(),
+            6 => SqlPointer::new(HANDLE_POINTER, Handle(name.clone())).into(),
+            7 => content.iter().rev().copied().collect::<Vec<u8>>().into(),
+            8 if name.ends_with(".txt") => SqlValue::StaticText("text"),
+            8 => SqlValue::StaticText("other"),
+            9 if content.is_empty() => SqlValue::StaticBlob(b""),
+            9 => SqlValue::StaticBlob(b"\x7fFILE"),
+            _ => SqlValue::Null,
+        })
+    }
+
+    fn column_nochange(&self, i: c_int) -> Result<Option<SqlValue<'_>>> {
+        // the content is the "expensive" column, only read when it's changed
+        if i == 1 {
+            return Ok(None);
+        }
+        self.column(i).map(Some)
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.idx as i64)
+    }
+}
+
+pub fn handle_name(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    let handle = unsafe { api::value_pointer::<Handle>(&values[0], HANDLE_POINTER) }
+        .ok_or("expected a file handle")?;
+    api::result_text(context, unsafe { &(*handle).0 })
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_typedcursor_init(db: *mut sqlite3) -> Result<()> {
+    ModuleBuilder::<FilesTable>::new("files")
+        .writeable()
+        .register(db)?;
+    define_scalar_function(db, "handle_name", 1, handle_name, FunctionFlags::UTF8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    fn content_reads() -> usize {
+        CONTENT_READS.swap(0, Ordering::SeqCst)
+    }
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(std::mem::transmute(
+                sqlite3_typedcursor_init as *const (),
+            )));
+        }
+
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "
+            create virtual table t using files();
+            insert into t(name, content) values ('a.txt', cast('hello' as blob));
+            insert into t(name, content) values ('bb.txt', x'00ff');
+            ",
+        )
+        .unwrap();
+
+        let row = db
+            .query_row(
+                "select name, content, size, ratio, typeof(ratio), json_type(stats, '$.size'),
+                   length(label), handle_name(handle), typeof(handle)
+                 from t where name = 'a.txt'",
+                [],
+                |r| {
+                    Ok((
+                        r.get::<_, String>(0)?,
+                        r.get::<_, Vec<u8>>(1)?,
+                        r.get::<_, i64>(2)?,
+                        r.get::<_, f64>(3)?,
+                        r.get::<_, String>(4)?,
+                        r.get::<_, String>(5)?,
+                        r.get::<_, i64>(6)?,
+                        r.get::<_, String>(7)?,
+                        r.get::<_, String>(8)?,
+                    ))
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            row,
+            (
+                "a.txt".to_owned(),
+                b"hello".to_vec(),
+                5,
+                1.0,
+                "real".to_owned(),
+                "integer".to_owned(),
+                // length() stops at the NUL, but the whole text was returned
+                5,
+                "a.txt".to_owned(),
+                "null".to_owned(),
+            )
+        );
+        let label: Vec<u8> = db
+            .query_row(
+                "select cast(label as blob) from t where name = 'bb.txt'",
+                [],
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(label, b"bb.txt\x002");
+        // JSON values keep their subtype, so they're embedded as objects
+        let stats: String = db
+            .query_row("select json_group_array(stats) from t", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(
+            stats,
+            r#"[{"name":"a.txt","size":5},{"name":"bb.txt","size":2}]"#
+        );
+        // borrowed values are still there after the cursor moves on
+        let names: String = db
+            .query_row("select max(name) || max(content) from t", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(names, "bb.txthello");
+        content_reads();
+
+        // the UPDATE doesn't change the content, so it isn't read
+        db.execute("update t set name = 'c.txt' where size = 2", [])
+            .unwrap();
+        assert_eq!(content_reads(), 0);
+        db.execute("update t set content = x'01' where name = 'c.txt'", [])
+            .unwrap();
+        let files: Vec<(String, Vec<u8>)> = db
+            .prepare("select name, content from t")
+            .unwrap()
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(
+            files,
+            [
+                ("a.txt".to_owned(), b"hello".to_vec()),
+                ("c.txt".to_owned(), vec![1])
+            ]
+        );
+
+        // owned blobs, including empty ones
+        db.execute("insert into t(name, content) values ('empty', x'')", [])
+            .unwrap();
+        let reversed: Vec<(Vec<u8>, String)> = db
+            .prepare("select reversed, typeof(reversed) from t")
+            .unwrap()
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(
+            reversed,
+            [
+                (b"olleh".to_vec(), "blob".to_owned()),
+                (vec![1], "blob".to_owned()),
+                (vec![], "blob".to_owned()),
+            ]
+        );
+
+        // static values are read in place, and outlive every row
+        let kinds: Vec<(String, Vec<u8>, String)> = db
+            .prepare("select kind, magic, typeof(magic) from t")
+            .unwrap()
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(
+            kinds,
+            [
+                ("text".to_owned(), b"\x7fFILE".to_vec(), "blob".to_owned()),
+                ("text".to_owned(), b"\x7fFILE".to_vec(), "blob".to_owned()),
+                ("other".to_owned(), vec![], "blob".to_owned()),
+            ]
+        );
+        let kind: String = db
+            .query_row("select min(kind) || max(kind) from t", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(kind, "othertext");
+    }
+}
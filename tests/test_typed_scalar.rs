@@ -0,0 +1,141 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{define_typed_scalar_function, Error, Result};
+
+#[sqlite_entrypoint]
+pub fn sqlite3_typedscalar_init(db: *mut sqlite3) -> Result<()> {
+    let flags = FunctionFlags::UTF8 | FunctionFlags::DETERMINISTIC;
+    define_typed_scalar_function(
+        db,
+        "xyz_add",
+        |a: i64, b: i64| -> Result<i64> { Ok(a + b) },
+        flags,
+    )?;
+    define_typed_scalar_function(
+        db,
+        "greet",
+        |name: &str| -> Result<String> { Ok(format!("hello, {}!", name)) },
+        flags,
+    )?;
+    define_typed_scalar_function(
+        db,
+        "half",
+        |x: Option<f64>| -> Result<Option<f64>> { Ok(x.map(|x| x / 2.0)) },
+        flags,
+    )?;
+    define_typed_scalar_function(
+        db,
+        "blob_len",
+        |b: &[u8]| -> Result<i32> { Ok(b.len() as i32) },
+        flags,
+    )?;
+    define_typed_scalar_function(
+        db,
+        "json_keys_len",
+        |value: serde_json::Value| -> Result<i64> {
+            match value {
+                serde_json::Value::Object(o) => Ok(o.len() as i64),
+                _ => Err(Error::new_message("expected a JSON object")),
+            }
+        },
+        flags,
+    )?;
+    define_typed_scalar_function(
+        db,
+        "is_even",
+        |i: i32| -> Result<bool> { Ok(i % 2 == 0) },
+        flags,
+    )?;
+    define_typed_scalar_function(db, "noop", || -> Result<()> { Ok(()) }, flags)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(std::mem::transmute(
+                sqlite3_typedscalar_init as *const (),
+            )));
+        }
+
+        let db = Connection::open_in_memory().unwrap();
+
+        let result: i64 = db
+            .query_row("select xyz_add(1, 2)", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(result, 3);
+
+        let result: String = db
+            .query_row("select greet('alex')", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(result, "hello, alex!");
+
+        let result: Option<f64> = db.query_row("select half(5)", [], |r| r.get(0)).unwrap();
+        assert_eq!(result, Some(2.5));
+        let result: Option<f64> = db.query_row("select half(null)", [], |r| r.get(0)).unwrap();
+        assert_eq!(result, None);
+
+        let result: i32 = db
+            .query_row("select blob_len(X'010203')", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(result, 3);
+        let result: i32 = db
+            .query_row("select blob_len(zeroblob(0))", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(result, 0);
+
+        let result: i64 = db
+            .query_row(r#"select json_keys_len('{"a": 1, "b": 2}')"#, [], |r| {
+                r.get(0)
+            })
+            .unwrap();
+        assert_eq!(result, 2);
+
+        let result: bool = db.query_row("select is_even(4)", [], |r| r.get(0)).unwrap();
+        assert!(result);
+
+        let result: Option<i64> = db.query_row("select noop()", [], |r| r.get(0)).unwrap();
+        assert_eq!(result, None);
+
+        // arity is inferred from the closure
+        let err = db
+            .query_row("select xyz_add(1)", [], |r| r.get::<_, i64>(0))
+            .unwrap_err();
+        assert!(err.to_string().contains("wrong number of arguments"));
+
+        // type mismatches are reported as SQL errors
+        let err = db
+            .query_row("select xyz_add(1, 'two')", [], |r| r.get::<_, i64>(0))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "xyz_add(): argument 2 expected integer, got text"
+        );
+
+        let err = db
+            .query_row("select greet(1)", [], |r| r.get::<_, String>(0))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "greet(): argument 1 expected text, got integer"
+        );
+
+        let err = db
+            .query_row("select is_even(10000000000)", [], |r| r.get::<_, bool>(0))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "is_even(): argument 1 integer 10000000000 out of range"
+        );
+
+        let err = db
+            .query_row("select json_keys_len('[1, 2]')", [], |r| r.get::<_, i64>(0))
+            .unwrap_err();
+        assert_eq!(err.to_string(), "expected a JSON object");
+    }
+}
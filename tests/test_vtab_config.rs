@@ -0,0 +1,146 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api, define_table_function,
+    table::{vtab_config, BestIndexError, IndexInfo, VTab, VTabArguments, VTabConfig, VTabCursor},
+    Result,
+};
+
+use std::{mem, os::raw::c_int};
+
+// select value from xxx;
+// A single row table function, that configures itself with the VTabConfig
+// given as its aux.
+#[repr(C)]
+pub struct OneTable {
+    base: sqlite3_vtab,
+}
+
+impl<'vtab> VTab<'vtab> for OneTable {
+    type Aux = VTabConfig;
+    type Cursor = OneCursor;
+
+    fn connect(
+        db: *mut sqlite3,
+        aux: Option<&Self::Aux>,
+        _args: VTabArguments,
+    ) -> Result<(String, OneTable)> {
+        if let Some(config) = aux {
+            vtab_config(db, *config)?;
+        }
+        let base: sqlite3_vtab = unsafe { mem::zeroed() };
+        Ok(("CREATE TABLE x(value)".to_owned(), OneTable { base }))
+    }
+
+    fn best_index(&mut self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        info.set_estimated_cost(1.0);
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<OneCursor> {
+        let base: sqlite3_vtab_cursor = unsafe { mem::zeroed() };
+        Ok(OneCursor { base, rowid: 0 })
+    }
+}
+
+#[repr(C)]
+pub struct OneCursor {
+    base: sqlite3_vtab_cursor,
+    rowid: i64,
+}
+
+impl VTabCursor for OneCursor {
+    fn filter(
+        &mut self,
+        _idx_num: c_int,
+        _idx_str: Option<&str>,
+        _values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        self.rowid = 1;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.rowid += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.rowid > 1
+    }
+
+    fn column(&self, context: *mut sqlite3_context, _i: c_int) -> Result<()> {
+        api::result_int64(context, self.rowid);
+        Ok(())
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.rowid)
+    }
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_vtabconfig_init(db: *mut sqlite3) -> Result<()> {
+    define_table_function::<OneTable>(db, "plain_fn", None)?;
+    define_table_function::<OneTable>(db, "innocuous_fn", Some(VTabConfig::Innocuous))?;
+    define_table_function::<OneTable>(db, "direct_fn", Some(VTabConfig::DirectOnly))?;
+    define_table_function::<OneTable>(db, "all_schemas_fn", Some(VTabConfig::UsesAllSchemas))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    fn select_one(db: &Connection, table: &str) -> rusqlite::Result<i64> {
+        db.query_row(&format!("select value from {}", table), [], |r| r.get(0))
+    }
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(std::mem::transmute(
+                sqlite3_vtabconfig_init as *const (),
+            )));
+        }
+
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "
+            create view v_plain as select value from plain_fn;
+            create view v_innocuous as select value from innocuous_fn;
+            create view v_direct as select value from direct_fn;
+            ",
+        )
+        .unwrap();
+
+        for table in ["plain_fn", "innocuous_fn", "direct_fn"] {
+            assert_eq!(select_one(&db, table).unwrap(), 1);
+        }
+
+        // SQLITE_VTAB_USES_ALL_SCHEMAS is rejected by older SQLite versions
+        if rusqlite::version_number() >= 3042000 {
+            assert_eq!(select_one(&db, "all_schemas_fn").unwrap(), 1);
+        } else {
+            assert_eq!(
+                select_one(&db, "all_schemas_fn").unwrap_err().to_string(),
+                "sqlite3_vtab_config(UsesAllSchemas) requires SQLite 3.42.0 or later"
+            );
+        }
+
+        // DIRECTONLY tables can never be used from views
+        assert_eq!(
+            select_one(&db, "v_direct").unwrap_err().to_string(),
+            "unsafe use of virtual table \"direct_fn\""
+        );
+
+        // and without a trusted schema, only INNOCUOUS tables can be
+        db.execute("pragma trusted_schema = off", []).unwrap();
+        assert_eq!(select_one(&db, "v_innocuous").unwrap(), 1);
+        assert_eq!(
+            select_one(&db, "v_plain").unwrap_err().to_string(),
+            "unsafe use of virtual table \"plain_fn\""
+        );
+    }
+}
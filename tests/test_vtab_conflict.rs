@@ -0,0 +1,190 @@
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api,
+    table::{
+        on_conflict, vtab_config, BestIndexError, ConflictMode, IndexInfo, UpdateOperation, VTab,
+        VTabArguments, VTabConfig, VTabCursor, VTabWriteable,
+    },
+    Error, ModuleBuilder, Result, SQLITE_CONSTRAINT_UNIQUE,
+};
+
+use std::{mem, os::raw::c_int};
+
+// create virtual table t using kv();
+// A key-value table where keys are unique, that supports INSERT OR REPLACE
+// and INSERT OR IGNORE.
+#[repr(C)]
+pub struct KvTable {
+    base: sqlite3_vtab,
+    db: *mut sqlite3,
+    entries: Vec<(String, String)>,
+}
+
+impl<'vtab> VTab<'vtab> for KvTable {
+    type Aux = ();
+    type Cursor = KvCursor;
+
+    fn connect(
+        db: *mut sqlite3,
+        _aux: Option<&Self::Aux>,
+        _args: VTabArguments,
+    ) -> Result<(String, KvTable)> {
+        vtab_config(db, VTabConfig::ConstraintSupport(true))?;
+        let base: sqlite3_vtab = unsafe { mem::zeroed() };
+        let vtab = KvTable {
+            base,
+            db,
+            entries: vec![],
+        };
+        Ok(("CREATE TABLE x(key, value)".to_owned(), vtab))
+    }
+
+    fn best_index(&mut self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        info.set_estimated_cost(100.0);
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<KvCursor> {
+        let base: sqlite3_vtab_cursor = unsafe { mem::zeroed() };
+        Ok(KvCursor {
+            base,
+            entries: self.entries.clone(),
+            idx: 0,
+        })
+    }
+}
+
+impl<'vtab> VTabWriteable<'vtab> for KvTable {
+    fn update(&mut self, operation: UpdateOperation, p_rowid: *mut i64) -> Result<()> {
+        let values = match operation {
+            UpdateOperation::Insert { values, .. } => values,
+            _ => return Err(Error::new_message("only inserts are supported")),
+        };
+        let key: String = values[0].get()?;
+        let value: String = values[1].get()?;
+
+        match self.entries.iter().position(|(k, _)| *k == key) {
+            None => {
+                self.entries.push((key, value));
+                unsafe { *p_rowid = self.entries.len() as i64 };
+            }
+            Some(idx) => match on_conflict(self.db) {
+                ConflictMode::Replace => {
+                    self.entries[idx].1 = value;
+                    unsafe { *p_rowid = idx as i64 + 1 };
+                }
+                ConflictMode::Ignore => (),
+                _ => {
+                    return Err(Error::new_code(
+                        SQLITE_CONSTRAINT_UNIQUE,
+                        format!("UNIQUE constraint failed: key {}", key),
+                    ))
+                }
+            },
+        }
+        Ok(())
+    }
+}
+
+#[repr(C)]
+pub struct KvCursor {
+    base: sqlite3_vtab_cursor,
+    entries: Vec<(String, String)>,
+    idx: usize,
+}
+
+impl VTabCursor for KvCursor {
+    fn filter(
+        &mut self,
+        _idx_num: c_int,
+        _idx_str: Option<&str>,
+        _values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        self.idx = 0;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.idx += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.idx >= self.entries.len()
+    }
+
+    fn column(&self, context: *mut sqlite3_context, i: c_int) -> Result<()> {
+        let (key, value) = &self.entries[self.idx];
+        api::result_text(context, if i == 0 { key } else { value })
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.idx as i64 + 1)
+    }
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_vtabconflict_init(db: *mut sqlite3) -> Result<()> {
+    ModuleBuilder::<KvTable>::new("kv").writeable().register(db)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    fn entries(db: &Connection) -> Vec<(String, String)> {
+        db.prepare("select key, value from t")
+            .unwrap()
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>, _>>()
+            .unwrap()
+    }
+
+    fn pair(key: &str, value: &str) -> (String, String) {
+        (key.to_owned(), value.to_owned())
+    }
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(std::mem::transmute(
+                sqlite3_vtabconflict_init as *const (),
+            )));
+        }
+
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "
+            create virtual table t using kv();
+            insert into t values ('a', '1'), ('b', '2');
+            insert or replace into t values ('a', '10');
+            insert or ignore into t values ('b', '20'), ('c', '30');
+            ",
+        )
+        .unwrap();
+        assert_eq!(
+            entries(&db),
+            [pair("a", "10"), pair("b", "2"), pair("c", "30")]
+        );
+
+        match db.execute("insert into t values ('c', '300')", []) {
+            Err(rusqlite::Error::SqliteFailure(err, Some(msg))) => {
+                assert_eq!(err.extended_code, SQLITE_CONSTRAINT_UNIQUE);
+                assert_eq!(msg, "UNIQUE constraint failed: key c");
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+
+        // vtab_config only works inside of create/connect
+        let err = vtab_config(
+            unsafe { db.handle().cast() },
+            VTabConfig::ConstraintSupport(true),
+        )
+        .unwrap_err();
+        assert_eq!(err.code(), sqlite_loadable::SQLITE_MISUSE);
+    }
+}
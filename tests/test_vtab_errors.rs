@@ -0,0 +1,201 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api, define_virtual_table_writeable,
+    table::{
+        BestIndexError, IndexInfo, UpdateOperation, VTab, VTabArguments, VTabCursor, VTabWriteable,
+    },
+    Error, Result, SQLITE_CONSTRAINT_VTAB,
+};
+
+use std::{mem, os::raw::c_int};
+
+// create virtual table t using errors(fail_on);
+// Fails with a message in the method that matches `fail_on`.
+#[repr(C)]
+pub struct ErrorsTable {
+    base: sqlite3_vtab,
+    fail_on: String,
+}
+
+impl ErrorsTable {
+    fn check(&self, method: &str) -> Result<()> {
+        if self.fail_on == method {
+            Err(Error::new_message(format!("{} failed: 100% %s %n", method)))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<'vtab> VTab<'vtab> for ErrorsTable {
+    type Aux = ();
+    type Cursor = ErrorsCursor;
+
+    fn create(
+        _db: *mut sqlite3,
+        _aux: Option<&Self::Aux>,
+        args: VTabArguments,
+    ) -> Result<(String, ErrorsTable)> {
+        let fail_on = args.arguments.get(0).cloned().unwrap_or_default();
+        if fail_on == "create" {
+            return Err(Error::new_message("create failed: 50%"));
+        }
+        let base: sqlite3_vtab = unsafe { mem::zeroed() };
+        let schema = if fail_on == "schema" {
+            "CREATE TABLE x(value"
+        } else {
+            "CREATE TABLE x(value)"
+        };
+        Ok((schema.to_owned(), ErrorsTable { base, fail_on }))
+    }
+
+    fn connect(
+        db: *mut sqlite3,
+        aux: Option<&Self::Aux>,
+        args: VTabArguments,
+    ) -> Result<(String, ErrorsTable)> {
+        Self::create(db, aux, args)
+    }
+
+    fn destroy(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn best_index(&mut self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        if self.fail_on == "best_index" {
+            return Err(BestIndexError::Error);
+        }
+        info.set_idxnum(1);
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<ErrorsCursor> {
+        self.check("open")?;
+        let base: sqlite3_vtab_cursor = unsafe { mem::zeroed() };
+        Ok(ErrorsCursor {
+            base,
+            fail_on: self.fail_on.clone(),
+            rowid: 0,
+        })
+    }
+}
+
+impl<'vtab> VTabWriteable<'vtab> for ErrorsTable {
+    fn update(&mut self, _operation: UpdateOperation, _p_rowid: *mut i64) -> Result<()> {
+        Err(Error::new_code(
+            SQLITE_CONSTRAINT_VTAB,
+            "table is read-only",
+        ))
+    }
+}
+
+#[repr(C)]
+pub struct ErrorsCursor {
+    base: sqlite3_vtab_cursor,
+    fail_on: String,
+    rowid: i64,
+}
+
+impl VTabCursor for ErrorsCursor {
+    fn filter(
+        &mut self,
+        _idx_num: c_int,
+        _idx_str: Option<&str>,
+        _values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        self.rowid = 1;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.rowid += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.rowid > 1
+    }
+
+    fn column(&self, context: *mut sqlite3_context, _i: c_int) -> Result<()> {
+        api::result_int64(context, self.rowid);
+        Ok(())
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        if self.fail_on == "rowid" {
+            return Err(Error::new_message("rowid failed"));
+        }
+        Ok(self.rowid)
+    }
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_vtaberrors_init(db: *mut sqlite3) -> Result<()> {
+    define_virtual_table_writeable::<ErrorsTable>(db, "errors", None)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(std::mem::transmute(
+                sqlite3_vtaberrors_init as *const (),
+            )));
+        }
+
+        let db = Connection::open_in_memory().unwrap();
+
+        let err = db
+            .execute("create virtual table t_create using errors(create)", [])
+            .unwrap_err();
+        assert_eq!(err.to_string(), "create failed: 50%");
+
+        // an invalid schema reports the error from sqlite3_declare_vtab
+        let err = db
+            .execute("create virtual table t_schema using errors(schema)", [])
+            .unwrap_err();
+        assert_eq!(err.to_string(), "incomplete input");
+
+        db.execute_batch(
+            "
+            create virtual table t_open using errors(open);
+            create virtual table t_rowid using errors(rowid);
+            create virtual table t_best_index using errors(best_index);
+            ",
+        )
+        .unwrap();
+
+        // '%' in messages are never interpreted as format directives
+        let err = db
+            .query_row("select value from t_open", [], |r| r.get::<_, i64>(0))
+            .unwrap_err();
+        assert_eq!(err.to_string(), "open failed: 100% %s %n");
+
+        let err = db
+            .query_row("select rowid from t_rowid", [], |r| r.get::<_, i64>(0))
+            .unwrap_err();
+        assert_eq!(err.to_string(), "rowid failed");
+
+        let err = db
+            .query_row("select value from t_best_index", [], |r| r.get::<_, i64>(0))
+            .unwrap_err();
+        assert_eq!(err.to_string(), "best_index failed");
+
+        match db
+            .execute("insert into t_rowid values (1)", [])
+            .unwrap_err()
+        {
+            rusqlite::Error::SqliteFailure(err, Some(msg)) => {
+                assert_eq!(err.extended_code, SQLITE_CONSTRAINT_VTAB);
+                assert_eq!(msg, "table is read-only");
+            }
+            err => panic!("unexpected error {:?}", err),
+        }
+    }
+}
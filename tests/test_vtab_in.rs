@@ -48,11 +48,11 @@ impl<'vtab> VTab<'vtab> for InTable {
         // TODO db.config(VTabConfig::Innocuous)?;
         Ok((CREATE_SQL.to_owned(), vtab))
     }
-    fn destroy(&self) -> Result<()> {
+    fn destroy(&mut self) -> Result<()> {
         Ok(())
     }
 
-    fn best_index(&self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+    fn best_index(&mut self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
         let mut idx_str = String::new();
         let mut argv_index = 1;
         for mut constraint in info.constraints() {
@@ -131,9 +131,8 @@ impl VTabCursor for InCursor {
             match constraint {
                 'Y' => {
                     let mut value = String::new();
-                    for v in InValues::new(values[idx]) {
-                        let v = v.unwrap();
-                        value.push_str(api::value_text(&v).unwrap());
+                    for v in InValues::<i64>::new(values[idx]) {
+                        value.push_str(&v?.to_string());
                     }
                     self.value = Some(value)
                 }
@@ -0,0 +1,189 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api,
+    table::{
+        BestIndexError, IndexInfo, ShadowTables, VTab, VTabArguments, VTabCursor, VTabIntegrity,
+        INTEGRITY_QUICK_CHECK,
+    },
+    ModuleBuilder, Result,
+};
+
+use std::{mem, os::raw::c_int};
+
+// create virtual table t using counts();
+// Sums up the non-negative counts stored in its "t_counts" shadow table, which
+// PRAGMA integrity_check verifies.
+#[repr(C)]
+pub struct CountsTable {
+    base: sqlite3_vtab,
+    shadow: ShadowTables,
+}
+
+impl<'vtab> VTab<'vtab> for CountsTable {
+    type Aux = ();
+    type Cursor = CountsCursor;
+
+    fn create(
+        db: *mut sqlite3,
+        aux: Option<&Self::Aux>,
+        args: VTabArguments,
+    ) -> Result<(String, CountsTable)> {
+        ShadowTables::new(db, &args).create_table("counts", "count integer")?;
+        Self::connect(db, aux, args)
+    }
+
+    fn connect(
+        db: *mut sqlite3,
+        _aux: Option<&Self::Aux>,
+        args: VTabArguments,
+    ) -> Result<(String, CountsTable)> {
+        let base: sqlite3_vtab = unsafe { mem::zeroed() };
+        let vtab = CountsTable {
+            base,
+            shadow: ShadowTables::new(db, &args),
+        };
+        Ok(("CREATE TABLE x(total)".to_owned(), vtab))
+    }
+
+    fn destroy(&mut self) -> Result<()> {
+        self.shadow.drop_table("counts")
+    }
+
+    fn best_index(&mut self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        info.set_estimated_cost(1.0);
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<CountsCursor> {
+        let total = self.shadow.query(
+            &format!(
+                "select total(count) from {}",
+                self.shadow.qualified_name("counts")
+            ),
+            |row| row[0].get::<f64>(),
+        )?;
+        let base: sqlite3_vtab_cursor = unsafe { mem::zeroed() };
+        Ok(CountsCursor {
+            base,
+            total: total[0] as i64,
+            rowid: 0,
+        })
+    }
+}
+
+impl<'vtab> VTabIntegrity<'vtab> for CountsTable {
+    fn integrity(&self, _schema: &str, table: &str, flags: c_int) -> Result<Option<String>> {
+        if flags & INTEGRITY_QUICK_CHECK != 0 {
+            return Ok(None);
+        }
+        let negatives = self.shadow.query(
+            &format!(
+                "select count(*) from {} where count < 0",
+                self.shadow.qualified_name("counts")
+            ),
+            |row| row[0].get::<i64>(),
+        )?;
+        Ok(match negatives[0] {
+            0 => None,
+            n => Some(format!("{} has {} negative counts", table, n)),
+        })
+    }
+}
+
+#[repr(C)]
+pub struct CountsCursor {
+    base: sqlite3_vtab_cursor,
+    total: i64,
+    rowid: i64,
+}
+
+impl VTabCursor for CountsCursor {
+    fn filter(
+        &mut self,
+        _idx_num: c_int,
+        _idx_str: Option<&str>,
+        _values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        self.rowid = 1;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.rowid += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.rowid > 1
+    }
+
+    fn column(&self, context: *mut sqlite3_context, _i: c_int) -> Result<()> {
+        api::result_int64(context, self.total);
+        Ok(())
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.rowid)
+    }
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_vtabintegrity_init(db: *mut sqlite3) -> Result<()> {
+    ModuleBuilder::<CountsTable>::new("counts")
+        .integrity()
+        .register(db)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    fn pragma(db: &Connection, pragma: &str) -> Vec<String> {
+        db.prepare(&format!("pragma {}", pragma))
+            .unwrap()
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(std::mem::transmute(
+                sqlite3_vtabintegrity_init as *const (),
+            )));
+        }
+
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "
+            create virtual table t using counts();
+            insert into t_counts values (1), (2);
+            ",
+        )
+        .unwrap();
+        assert_eq!(pragma(&db, "integrity_check"), ["ok"]);
+
+        db.execute("insert into t_counts values (-1), (-2)", [])
+            .unwrap();
+        assert_eq!(
+            db.query_row("select total from t", [], |r| r.get::<_, i64>(0))
+                .unwrap(),
+            0
+        );
+        // quick_check skips the (pretend) expensive check
+        assert_eq!(pragma(&db, "quick_check"), ["ok"]);
+
+        // xIntegrity is only called by SQLite 3.44.0 and up
+        if rusqlite::version_number() >= 3044000 {
+            let problems = pragma(&db, "integrity_check");
+            assert_eq!(problems.len(), 1);
+            assert!(problems[0].contains("t has 2 negative counts"));
+        } else {
+            assert_eq!(pragma(&db, "integrity_check"), ["ok"]);
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api,
+    table::{BestIndexError, IndexInfo, VTab, VTabArguments, VTabCursor, VTabRename},
+    Error, ModuleBuilder, Result,
+};
+
+use std::{mem, os::raw::c_int};
+
+fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+// create virtual table t using stored();
+// Keeps its (pretend) data in a regular "t_data" table, that follows the
+// virtual table when it's renamed. Its one row is the current table name.
+#[repr(C)]
+pub struct StoredTable {
+    base: sqlite3_vtab,
+    db: *mut sqlite3,
+    schema: String,
+    name: String,
+}
+
+impl StoredTable {
+    fn data_table(&self, name: &str) -> String {
+        format!(
+            "{}.{}",
+            quote(&self.schema),
+            quote(&format!("{}_data", name))
+        )
+    }
+}
+
+impl<'vtab> VTab<'vtab> for StoredTable {
+    type Aux = ();
+    type Cursor = StoredCursor;
+
+    fn create(
+        db: *mut sqlite3,
+        aux: Option<&Self::Aux>,
+        args: VTabArguments,
+    ) -> Result<(String, StoredTable)> {
+        let (sql, vtab) = Self::connect(db, aux, args)?;
+        api::exec(
+            db,
+            &format!("create table {}(value)", vtab.data_table(&vtab.name)),
+        )?;
+        Ok((sql, vtab))
+    }
+
+    fn connect(
+        db: *mut sqlite3,
+        _aux: Option<&Self::Aux>,
+        args: VTabArguments,
+    ) -> Result<(String, StoredTable)> {
+        let base: sqlite3_vtab = unsafe { mem::zeroed() };
+        let vtab = StoredTable {
+            base,
+            db,
+            schema: args.database_name,
+            name: args.table_name,
+        };
+        Ok(("CREATE TABLE x(name)".to_owned(), vtab))
+    }
+
+    fn destroy(&mut self) -> Result<()> {
+        api::exec(
+            self.db,
+            &format!("drop table {}", self.data_table(&self.name)),
+        )
+    }
+
+    fn best_index(&mut self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        info.set_estimated_cost(1.0);
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<StoredCursor> {
+        let base: sqlite3_vtab_cursor = unsafe { mem::zeroed() };
+        Ok(StoredCursor {
+            base,
+            name: self.name.clone(),
+            rowid: 0,
+        })
+    }
+}
+
+impl<'vtab> VTabRename<'vtab> for StoredTable {
+    fn rename(&mut self, new_name: &str) -> Result<()> {
+        if new_name.starts_with("bad") {
+            return Err(Error::new_message(format!("can't rename to {}", new_name)));
+        }
+        api::exec(
+            self.db,
+            &format!(
+                "alter table {} rename to {}",
+                self.data_table(&self.name),
+                quote(&format!("{}_data", new_name))
+            ),
+        )?;
+        self.name = new_name.to_owned();
+        Ok(())
+    }
+}
+
+#[repr(C)]
+pub struct StoredCursor {
+    base: sqlite3_vtab_cursor,
+    name: String,
+    rowid: i64,
+}
+
+impl VTabCursor for StoredCursor {
+    fn filter(
+        &mut self,
+        _idx_num: c_int,
+        _idx_str: Option<&str>,
+        _values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        self.rowid = 1;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.rowid += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.rowid > 1
+    }
+
+    fn column(&self, context: *mut sqlite3_context, _i: c_int) -> Result<()> {
+        api::result_text(context, &self.name)
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.rowid)
+    }
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_vtabrename_init(db: *mut sqlite3) -> Result<()> {
+    ModuleBuilder::<StoredTable>::new("stored")
+        .renameable()
+        .register(db)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    fn tables(db: &Connection) -> Vec<String> {
+        db.prepare("select name from sqlite_master where type = 'table' order by name")
+            .unwrap()
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(std::mem::transmute(
+                sqlite3_vtabrename_init as *const (),
+            )));
+        }
+
+        let db = Connection::open_in_memory().unwrap();
+        db.execute("create virtual table t using stored()", [])
+            .unwrap();
+        assert_eq!(tables(&db), ["t", "t_data"]);
+
+        db.execute("alter table t rename to u", []).unwrap();
+        assert_eq!(tables(&db), ["u", "u_data"]);
+        let name: String = db
+            .query_row("select name from u", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(name, "u");
+
+        // errors from rename() abort the ALTER TABLE
+        let err = db.execute("alter table u rename to bad", []).unwrap_err();
+        assert_eq!(err.to_string(), "can't rename to bad");
+        assert_eq!(tables(&db), ["u", "u_data"]);
+
+        // and the backing table is renamed in the same transaction
+        db.execute_batch("begin; alter table u rename to v;")
+            .unwrap();
+        assert_eq!(tables(&db), ["v", "v_data"]);
+        db.execute("rollback", []).unwrap();
+        assert_eq!(tables(&db), ["u", "u_data"]);
+
+        db.execute("drop table u", []).unwrap();
+        assert!(tables(&db).is_empty());
+    }
+}
@@ -0,0 +1,216 @@
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api,
+    table::{
+        BestIndexError, IndexInfo, UpdateOperation, VTab, VTabArguments, VTabCursor, VTabWriteable,
+    },
+    ModuleBuilder, Result,
+};
+
+use std::{collections::BTreeMap, mem, os::raw::c_int, sync::Mutex};
+
+/// Every decoded xUpdate call, formatted as a string
+static OPERATIONS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+// create virtual table t using docs();
+// A (name, body) table, where "body" is pretend-expensive to compute, so it's
+// skipped in xColumn when an UPDATE doesn't change it.
+#[repr(C)]
+pub struct DocsTable {
+    base: sqlite3_vtab,
+    rows: BTreeMap<i64, (String, String)>,
+}
+
+impl<'vtab> VTab<'vtab> for DocsTable {
+    type Aux = ();
+    type Cursor = DocsCursor;
+
+    fn connect(
+        _db: *mut sqlite3,
+        _aux: Option<&Self::Aux>,
+        _args: VTabArguments,
+    ) -> Result<(String, DocsTable)> {
+        let base: sqlite3_vtab = unsafe { mem::zeroed() };
+        let vtab = DocsTable {
+            base,
+            rows: BTreeMap::new(),
+        };
+        Ok(("CREATE TABLE x(name, body)".to_owned(), vtab))
+    }
+
+    fn best_index(&mut self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        info.set_estimated_cost(100.0);
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<DocsCursor> {
+        let base: sqlite3_vtab_cursor = unsafe { mem::zeroed() };
+        Ok(DocsCursor {
+            base,
+            rows: self
+                .rows
+                .iter()
+                .map(|(rowid, row)| (*rowid, row.clone()))
+                .collect(),
+            idx: 0,
+        })
+    }
+}
+
+fn describe(value: &Option<api::Value>) -> String {
+    match value {
+        Some(value) => value.get::<String>().unwrap(),
+        None => "<unchanged>".to_owned(),
+    }
+}
+
+impl<'vtab> VTabWriteable<'vtab> for DocsTable {
+    fn update(&mut self, operation: UpdateOperation, p_rowid: *mut i64) -> Result<()> {
+        let description = match operation {
+            UpdateOperation::Delete { rowid } => {
+                let rowid = rowid.get::<i64>()?;
+                self.rows.remove(&rowid);
+                format!("delete {}", rowid)
+            }
+            UpdateOperation::Insert { rowid, values } => {
+                let requested = rowid.as_ref().map(|rowid| rowid.get::<i64>()).transpose()?;
+                let rowid =
+                    requested.unwrap_or_else(|| self.rows.keys().last().map_or(1, |last| last + 1));
+                let row = (values[0].get::<String>()?, values[1].get::<String>()?);
+                self.rows.insert(rowid, row);
+                unsafe { *p_rowid = rowid };
+                format!("insert {:?} {:?}", requested, values.len())
+            }
+            UpdateOperation::Update {
+                old_rowid,
+                new_rowid,
+                values,
+            } => {
+                let old_rowid = old_rowid.get::<i64>()?;
+                let new_rowid = new_rowid.get::<i64>()?;
+                let (mut name, mut body) = self
+                    .rows
+                    .remove(&old_rowid)
+                    .ok_or_else(|| format!("no row {}", old_rowid))?;
+                if let Some(value) = &values[0] {
+                    name = value.get()?;
+                }
+                if let Some(value) = &values[1] {
+                    body = value.get()?;
+                }
+                self.rows.insert(new_rowid, (name, body));
+                format!(
+                    "update {} -> {} ({}, {})",
+                    old_rowid,
+                    new_rowid,
+                    describe(&values[0]),
+                    describe(&values[1])
+                )
+            }
+        };
+        OPERATIONS.lock().unwrap().push(description);
+        Ok(())
+    }
+}
+
+#[repr(C)]
+pub struct DocsCursor {
+    base: sqlite3_vtab_cursor,
+    rows: Vec<(i64, (String, String))>,
+    idx: usize,
+}
+
+impl VTabCursor for DocsCursor {
+    fn filter(
+        &mut self,
+        _idx_num: c_int,
+        _idx_str: Option<&str>,
+        _values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        self.idx = 0;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.idx += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.idx >= self.rows.len()
+    }
+
+    fn column(&self, context: *mut sqlite3_context, i: c_int) -> Result<()> {
+        let (_, (name, body)) = &self.rows[self.idx];
+        match i {
+            0 => api::result_text(context, name),
+            // the UPDATE doesn't need the body, so don't "compute" it
+            _ if api::vtab_nochange(context) => Ok(()),
+            _ => api::result_text(context, body),
+        }
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.rows[self.idx].0)
+    }
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_vtabupdate_init(db: *mut sqlite3) -> Result<()> {
+    ModuleBuilder::<DocsTable>::new("docs")
+        .writeable()
+        .register(db)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    fn rows(db: &Connection) -> Vec<(i64, String, String)> {
+        db.prepare("select rowid, name, body from t")
+            .unwrap()
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(std::mem::transmute(
+                sqlite3_vtabupdate_init as *const (),
+            )));
+        }
+
+        let db = Connection::open_in_memory().unwrap();
+        db.execute("create virtual table t using docs()", [])
+            .unwrap();
+
+        db.execute_batch(
+            "
+            insert into t(rowid, name, body) values (10, 'a', 'aaa');
+            insert into t(name, body) values ('b', 'bbb');
+            update t set name = 'A' where rowid = 10;
+            update t set rowid = 20, body = 'BBB' where rowid = 11;
+            delete from t where rowid = 10;
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(
+            *OPERATIONS.lock().unwrap(),
+            [
+                "insert Some(10) 2",
+                "insert None 2",
+                "update 10 -> 10 (A, <unchanged>)",
+                "update 11 -> 20 (b, BBB)",
+                "delete 10",
+            ]
+        );
+        assert_eq!(rows(&db), [(20, "b".to_owned(), "BBB".to_owned())]);
+    }
+}
@@ -0,0 +1,96 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{api, define_window_function, Aggregate, Context, Result, WindowFunction};
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static STEPS: AtomicUsize = AtomicUsize::new(0);
+
+// moving_sum(value)
+#[derive(Default)]
+pub struct MovingSum {
+    total: i64,
+}
+
+impl Aggregate for MovingSum {
+    fn step(&mut self, _context: &mut Context, values: &[*mut sqlite3_value]) -> Result<()> {
+        STEPS.fetch_add(1, Ordering::SeqCst);
+        self.total += api::value_int64(&values[0]);
+        Ok(())
+    }
+
+    fn finalize(self, context: &mut Context) -> Result<()> {
+        context.result_int64(self.total);
+        Ok(())
+    }
+}
+
+impl WindowFunction for MovingSum {
+    fn value(&self, context: &mut Context) -> Result<()> {
+        context.result_int64(self.total);
+        Ok(())
+    }
+
+    fn inverse(&mut self, _context: &mut Context, values: &[*mut sqlite3_value]) -> Result<()> {
+        self.total -= api::value_int64(&values[0]);
+        Ok(())
+    }
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_window_init(db: *mut sqlite3) -> Result<()> {
+    define_window_function::<MovingSum>(db, "moving_sum", 1, FunctionFlags::UTF8)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(std::mem::transmute(sqlite3_window_init as *const ())));
+        }
+
+        let db = Connection::open_in_memory().unwrap();
+
+        let result: i64 = db
+            .query_row(
+                "select moving_sum(value) from json_each('[1, 2, 3, 4]')",
+                [],
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(result, 10);
+
+        STEPS.store(0, Ordering::SeqCst);
+        let result: Vec<i64> = db
+            .prepare(
+                "select moving_sum(value) over (order by key rows between 1 preceding and current row)
+                from json_each('[1, 2, 3, 4, 5]')",
+            )
+            .unwrap()
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(result, [1, 3, 5, 7, 9]);
+        // every row is only stepped into the frame once
+        assert_eq!(STEPS.load(Ordering::SeqCst), 5);
+
+        // frames that are empty call xValue before any xStep
+        let result: Vec<i64> = db
+            .prepare(
+                "select moving_sum(value) over (order by key rows between 2 preceding and 1 preceding)
+                from json_each('[1, 2, 3]')",
+            )
+            .unwrap()
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(result, [0, 1, 3]);
+    }
+}